@@ -2,8 +2,25 @@
 
 layout (location=0) out vec4 theColour;
 
-layout (location=0) in vec4 data_from_the_vertexshader;
+layout (location=0) in vec4 colour_from_the_vertexshader;
+layout (location=1) in vec2 uv_from_the_vertexshader;
+
+// 0 shows all channels, 1-4 show only red, green, blue or alpha as grayscale
+layout (push_constant) uniform DebugView {
+    uint channel;
+} debug_view;
 
 void main(){
-    theColour = data_from_the_vertexshader;
+    // checkerboard from the UVs so the interpolation is visible
+    vec2 cell = floor(uv_from_the_vertexshader * 8.0);
+    float checker = mod(cell.x + cell.y, 2.0);
+    // only the colour is shaded, the alpha is passed on unchanged
+    vec4 colour = vec4(colour_from_the_vertexshader.rgb * (0.75 + 0.25 * checker),
+                       colour_from_the_vertexshader.a);
+    if (debug_view.channel == 0u) {
+        theColour = colour;
+    } else {
+        float value = colour[debug_view.channel - 1u];
+        theColour = vec4(value, value, value, 1.0);
+    }
 }
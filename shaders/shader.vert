@@ -1,9 +1,14 @@
 #version 450
 
-layout (location=0) out vec4 data_from_the_vertexshader;
+layout (location=0) in vec2 position;
+layout (location=1) in vec4 colour;
+layout (location=2) in vec2 uv;
+
+layout (location=0) out vec4 colour_from_the_vertexshader;
+layout (location=1) out vec2 uv_from_the_vertexshader;
 
 void main() {
-    gl_PointSize=200.0;
-    gl_Position = vec4(0.0,0.0,0.0,1.0);
-    data_from_the_vertexshader = vec4(0.0,0.6,1.0,1.0);
+    gl_Position = vec4(position, 0.0, 1.0);
+    colour_from_the_vertexshader = colour;
+    uv_from_the_vertexshader = uv;
 }
@@ -0,0 +1,23 @@
+//! A small Vulkan renderer built on `ash`, `winit` and `gpu-allocator`.
+//!
+//! Create a [`VulkanRenderer`] from a `winit` window, either with
+//! [`VulkanRenderer::new`] or configured through a [`RendererBuilder`], then call
+//! [`VulkanRenderer::render_frame`] from your own event loop whenever the
+//! window should be redrawn. The building blocks ([`Device`], [`Swapchain`],
+//! [`Pipeline`], ...) are public for applications that need to reach
+//! further in.
+
+pub mod renderer;
+
+pub use renderer::buffer::{Buffer, SharedAllocator};
+pub use renderer::builder::RendererBuilder;
+pub use renderer::command_pools::CommandPools;
+pub use renderer::debug::{DebugMessage, DebugSettings, DisabledFeature};
+pub use renderer::device::{Device, DeviceFeatures, DeviceSelector, PhysicalDeviceInfo, QueueKind};
+pub use renderer::error::RendererError;
+pub use renderer::hooks::{HookContext, InjectionPoint, RenderHook};
+pub use renderer::staging::StagingBuffer;
+pub use renderer::pipeline::{Pipeline, PipelineSettings};
+pub use renderer::swapchain::{PresentMode, Swapchain, SwapchainSettings, SwapchainStatus};
+pub use renderer::vertex::{ColoredVertex, Vertex, VertexBuffer};
+pub use renderer::VulkanRenderer;
@@ -1,14 +1,19 @@
-mod renderer;
-
-use ash::vk;
-use renderer::VulkanRenderer;
+use vulkanrender::{PresentMode, RendererBuilder};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let eventloop = winit::event_loop::EventLoop::new();
     let window = winit::window::Window::new(&eventloop)?;
-    let mut renderer = VulkanRenderer::new(window)?;
+    let mut renderer = RendererBuilder::new()
+        .app_name("The Black Window")
+        .debug_callback(|message| println!("{}", message))
+        .build(window)?;
+    for disabled in renderer.disabled_features() {
+        eprintln!("warning: {}", disabled);
+    }
+    renderer.window.set_title("The Black Window");
+    renderer.window.set_show_fps(true);
 
-    use winit::event::{Event, WindowEvent};
+    use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
     eventloop.run(move |event, _, controlflow| match event {
         Event::WindowEvent { 
             event: WindowEvent::CloseRequested,
@@ -16,81 +21,84 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         } => {
             *controlflow = winit::event_loop::ControlFlow::Exit;
         },
+        Event::WindowEvent {
+            event: WindowEvent::ScaleFactorChanged { scale_factor, new_inner_size },
+            ..
+        } => {
+            renderer.scale_factor_changed(scale_factor, *new_inner_size);
+        },
+        Event::WindowEvent {
+            event: WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::C),
+                    ..
+                },
+                ..
+            },
+            ..
+        } => {
+            let channel_view = renderer.pipeline.channel_view.next();
+            renderer.set_channel_view(channel_view);
+        },
+        Event::WindowEvent {
+            event: WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::V),
+                    ..
+                },
+                ..
+            },
+            ..
+        } => {
+            let vsync = renderer.swapchain.settings.present_mode == PresentMode::Fifo;
+            renderer.set_vsync(!vsync);
+        },
+        Event::WindowEvent {
+            event: WindowEvent::DroppedFile(path),
+            ..
+        } => {
+            renderer.window.file_dropped(path);
+        },
+        Event::WindowEvent {
+            event: WindowEvent::Resized(physical_size),
+            ..
+        } => {
+            renderer.resized(physical_size);
+        },
+        Event::WindowEvent {
+            event: WindowEvent::Moved(_),
+            ..
+        } => {
+            // the window may be on a monitor with another refresh rate now
+            renderer.display_changed();
+        },
         Event::MainEventsCleared => {
             // doing the work here
-            renderer.window.request_redraw();
+            if *controlflow == winit::event_loop::ControlFlow::Exit {
+                return;
+            }
+            if renderer.is_minimized() {
+                // nothing to draw, sleep until the window is restored
+                *controlflow = winit::event_loop::ControlFlow::Wait;
+            } else if let Some(next_frame_at) = renderer
+                .next_frame_at()
+                .filter(|&at| at > std::time::Instant::now())
+            {
+                // without vsync, do not draw faster than the display refreshes
+                *controlflow = winit::event_loop::ControlFlow::WaitUntil(next_frame_at);
+            } else {
+                *controlflow = winit::event_loop::ControlFlow::Poll;
+                renderer.window.window().request_redraw();
+            }
         },
         Event::RedrawRequested(_) => {
             // render here
-            let (image_index, _) = unsafe {
-                renderer
-                    .swapchain
-                    .swapchain_loader
-                    .acquire_next_image(
-                        renderer.swapchain.swapchain, 
-                        std::u64::MAX,
-                        renderer.swapchain.image_available[renderer.swapchain.current_image],
-                        vk::Fence::null()
-                    )
-                    .expect("image aquisition trouble")
-            };
-            unsafe {
-                renderer
-                    .device
-                    .logical_device
-                    .wait_for_fences(
-                        &[renderer.swapchain.may_begin_drawing[renderer.swapchain.current_image]],
-                        true, 
-                        std::u64::MAX
-                    )
-                    .expect("fence wainting");
-
-                renderer
-                    .device
-                    .logical_device
-                    .reset_fences(&[
-                        renderer.swapchain.may_begin_drawing[renderer.swapchain.current_image]
-                    ])
-                    .expect("resetting fences");
-            };
-            let semaphores_available = 
-                [renderer.swapchain.image_available[renderer.swapchain.current_image]];
-            let waiting_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-            let semaphores_finished = 
-                [renderer.swapchain.rendering_finished[renderer.swapchain.current_image]];
-            let commandbuffers = [renderer.commandbuffers[image_index as usize]];
-            let submit_info = [vk::SubmitInfo::builder()
-                .wait_semaphores(&semaphores_available)
-                .wait_dst_stage_mask(&waiting_stages)
-                .command_buffers(&commandbuffers)
-                .signal_semaphores(&semaphores_finished)
-                .build()];
-            unsafe {
-                renderer
-                    .device
-                    .logical_device
-                    .queue_submit(
-                        renderer.device.queues.graphics_queue,
-                        &submit_info, 
-                        renderer.swapchain.may_begin_drawing[renderer.swapchain.current_image]
-                    )
-                    .expect("queue submission");
-            };
-            let swapchains = [renderer.swapchain.swapchain];
-            let indices = [image_index];
-            let present_info = vk::PresentInfoKHR::builder()
-                .wait_semaphores(&semaphores_finished)
-                .swapchains(&swapchains)
-                .image_indices(&indices);
-            unsafe {
-                renderer
-                    .swapchain
-                    .swapchain_loader
-                    .queue_present(renderer.device.queues.graphics_queue, &present_info)
-                    .expect("queue presentation");
+            if let Err(err) = renderer.render_frame() {
+                eprintln!("rendering a frame: {}", err);
+                *controlflow = winit::event_loop::ControlFlow::Exit;
             }
-            renderer.swapchain.current_image =
-                (renderer.swapchain.current_image + 1) % renderer.swapchain.amount_of_images as usize;
         },
         _ => {}
     });
@@ -0,0 +1,164 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use ash::vk;
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, Allocator};
+use gpu_allocator::MemoryLocation;
+
+use super::device::Device;
+use super::error::RendererError;
+use super::staging::StagingBuffer;
+
+/// The renderer's allocator, shared with the buffers allocated from it so
+/// they can free their memory when dropped.
+pub type SharedAllocator = Arc<Mutex<Allocator>>;
+
+/// A `vk::Buffer` bound to its own allocation from the renderer's allocator.
+/// Vertex, index, uniform and storage buffers all go through this type.
+///
+/// Dropping it frees the memory and destroys the buffer right away, so the
+/// GPU must be done with it by then, and it must not outlive the device.
+pub struct Buffer {
+    pub buffer: vk::Buffer,
+    /// Size requested at creation in bytes; the allocation may be larger.
+    pub size: u64,
+    pub usage: vk::BufferUsageFlags,
+    allocation: Option<Allocation>,
+    logical_device: ash::Device,
+    allocator: SharedAllocator,
+}
+
+impl Buffer {
+    /// Creates a buffer from `buffer_info` and binds it to new memory at
+    /// `location`; `name` shows up in the allocator's reports. The size must
+    /// not be zero, or `EmptyBuffer` is returned.
+    pub fn new(
+        logical_device: &ash::Device,
+        allocator: &SharedAllocator,
+        name: &str,
+        buffer_info: &vk::BufferCreateInfo,
+        location: MemoryLocation,
+    ) -> Result<Buffer, RendererError> {
+        if buffer_info.size == 0 {
+            return Err(RendererError::EmptyBuffer);
+        }
+        let buffer = unsafe { logical_device.create_buffer(buffer_info, None) }
+            .map_err(RendererError::Buffer)?;
+        // dropped, and so destroyed, if allocating or binding its memory fails
+        let mut buffer = Buffer {
+            buffer,
+            size: buffer_info.size,
+            usage: buffer_info.usage,
+            allocation: None,
+            logical_device: logical_device.clone(),
+            allocator: allocator.clone(),
+        };
+        buffer.allocate(name, location)?;
+        Ok(buffer)
+    }
+
+    /// Creates a host-visible buffer for `data` and copies it in.
+    pub fn host_visible<T: Copy>(
+        logical_device: &ash::Device,
+        allocator: &SharedAllocator,
+        name: &str,
+        usage: vk::BufferUsageFlags,
+        data: &[T],
+    ) -> Result<Buffer, RendererError> {
+        let buffer_info = vk::BufferCreateInfo::builder()
+            .size(std::mem::size_of_val(data) as u64)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let mut buffer = Self::new(logical_device, allocator, name, &buffer_info, MemoryLocation::CpuToGpu)?;
+        buffer.write(0, data)?;
+        Ok(buffer)
+    }
+
+    /// Creates a device-local buffer for `data` and fills it through
+    /// `staging` on the transfer queue.
+    pub fn device_local<T: Copy>(
+        device: &Device,
+        allocator: &SharedAllocator,
+        staging: &mut StagingBuffer,
+        name: &str,
+        usage: vk::BufferUsageFlags,
+        data: &[T],
+    ) -> Result<Buffer, RendererError> {
+        let logical_device = &device.logical_device;
+        let queue_families = &device.queue_families;
+        let queuefamilies: Vec<u32> = [queue_families.graphics_q_index, queue_families.transfer_q_index]
+            .into_iter()
+            .flatten()
+            .collect();
+        // written on the transfer queue and read on the graphics queue
+        let buffer_info = if queue_families.separate_transfer_family() {
+            vk::BufferCreateInfo::builder()
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&queuefamilies)
+        } else {
+            vk::BufferCreateInfo::builder().sharing_mode(vk::SharingMode::EXCLUSIVE)
+        }
+        .size(std::mem::size_of_val(data) as u64)
+        .usage(usage | vk::BufferUsageFlags::TRANSFER_DST);
+        let buffer = Self::new(logical_device, allocator, name, &buffer_info, MemoryLocation::GpuOnly)?;
+        staging.upload(device, allocator, buffer.buffer, data)?;
+        Ok(buffer)
+    }
+
+    fn allocate(&mut self, name: &str, location: MemoryLocation) -> Result<(), RendererError> {
+        let logical_device = &self.logical_device;
+        let requirements = unsafe { logical_device.get_buffer_memory_requirements(self.buffer) };
+        let mut allocator = self.allocator.lock().unwrap_or_else(PoisonError::into_inner);
+        let allocation = allocator.allocate(&AllocationCreateDesc {
+            name,
+            requirements,
+            location,
+            linear: true,
+        })?;
+        drop(allocator);
+        let allocation = self.allocation.insert(allocation);
+        unsafe {
+            logical_device.bind_buffer_memory(self.buffer, allocation.memory(), allocation.offset())
+        }.map_err(RendererError::Buffer)
+    }
+
+    /// Where the memory is mapped, if it is host-visible.
+    pub fn mapped_ptr(&self) -> Option<std::ptr::NonNull<std::ffi::c_void>> {
+        self.allocation.as_ref().and_then(Allocation::mapped_ptr)
+    }
+
+    /// Copies `data` into host-visible memory starting `offset` bytes in.
+    /// The GPU must not be using that range.
+    pub fn write<T: Copy>(&mut self, offset: u64, data: &[T]) -> Result<(), RendererError> {
+        let len = std::mem::size_of_val(data) as u64;
+        if offset.checked_add(len).is_none_or(|end| end > self.size) {
+            return Err(RendererError::OutOfBounds {
+                offset,
+                len,
+                size: self.size,
+            });
+        }
+        let mapped = self
+            .mapped_ptr()
+            .ok_or(RendererError::NotHostVisible)?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr() as *const u8,
+                (mapped.as_ptr() as *mut u8).add(offset as usize),
+                len as usize,
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        if let Some(allocation) = self.allocation.take() {
+            // a panic elsewhere must not keep the memory from being freed
+            let mut allocator = self.allocator.lock().unwrap_or_else(PoisonError::into_inner);
+            // the allocator only fails to free allocations it does not know
+            let _ = allocator.free(allocation);
+        }
+        unsafe { self.logical_device.destroy_buffer(self.buffer, None) };
+    }
+}
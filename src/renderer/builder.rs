@@ -0,0 +1,166 @@
+use std::ffi::{CStr, CString};
+
+use ash::vk;
+
+use super::debug::{DebugCallback, DebugMessage, DebugSettings};
+use super::device::{DeviceFeatures, DeviceSelector};
+use super::error::RendererError;
+use super::pipeline::PipelineSettings;
+use super::swapchain::{PresentMode, SwapchainSettings};
+use super::VulkanRenderer;
+
+/// Configures and creates a [`VulkanRenderer`].
+///
+/// ```no_run
+/// # let eventloop = winit::event_loop::EventLoop::new();
+/// # let window = winit::window::Window::new(&eventloop).unwrap();
+/// let renderer = vulkanrender::RendererBuilder::new()
+///     .app_name("My App")
+///     .clear_color([0.1, 0.1, 0.1, 1.0])
+///     .build(window)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct RendererBuilder {
+    pub(crate) app_name: String,
+    pub(crate) app_version: u32,
+    pub(crate) api_version: u32,
+    pub(crate) layers: Vec<CString>,
+    pub(crate) instance_extensions: Vec<CString>,
+    pub(crate) device_extensions: Vec<CString>,
+    pub(crate) device_selector: Option<DeviceSelector>,
+    pub(crate) device_features: DeviceFeatures,
+    pub(crate) debug_settings: DebugSettings,
+    pub(crate) debug_callback: Option<DebugCallback>,
+    pub(crate) swapchain_settings: SwapchainSettings,
+    pub(crate) pipeline_settings: PipelineSettings,
+    pub(crate) clear_color: [f32; 4],
+}
+
+impl Default for RendererBuilder {
+    fn default() -> Self {
+        RendererBuilder {
+            app_name: "The Black Window".to_owned(),
+            app_version: vk::make_api_version(0, 0, 1, 0),
+            api_version: vk::API_VERSION_1_1,
+            layers: vec![],
+            instance_extensions: vec![],
+            device_extensions: vec![],
+            device_selector: None,
+            device_features: DeviceFeatures::default(),
+            debug_settings: DebugSettings::default(),
+            debug_callback: None,
+            swapchain_settings: SwapchainSettings::default(),
+            pipeline_settings: PipelineSettings::default(),
+            clear_color: [0.0, 0.0, 0.08, 1.0],
+        }
+    }
+}
+
+impl RendererBuilder {
+    pub fn new() -> RendererBuilder {
+        Self::default()
+    }
+
+    pub fn app_name(mut self, app_name: &str) -> Self {
+        self.app_name = app_name.to_owned();
+        self
+    }
+
+    pub fn app_version(mut self, major: u32, minor: u32, patch: u32) -> Self {
+        self.app_version = vk::make_api_version(0, major, minor, patch);
+        self
+    }
+
+    /// Vulkan version to request, e.g. `vk::API_VERSION_1_2`.
+    pub fn api_version(mut self, api_version: u32) -> Self {
+        self.api_version = api_version;
+        self
+    }
+
+    /// Enables an additional instance layer.
+    pub fn layer(mut self, layer_name: &CStr) -> Self {
+        self.layers.push(layer_name.to_owned());
+        self
+    }
+
+    /// Enables an instance extension on top of the ones the renderer needs.
+    pub fn instance_extension(mut self, extension_name: &CStr) -> Self {
+        self.instance_extensions.push(extension_name.to_owned());
+        self
+    }
+
+    /// Enables a device extension on top of the ones the renderer needs.
+    pub fn device_extension(mut self, extension_name: &CStr) -> Self {
+        self.device_extensions.push(extension_name.to_owned());
+        self
+    }
+
+    /// Turns the Khronos validation layer and the debug messenger on or off.
+    pub fn validation(mut self, validation: bool) -> Self {
+        self.debug_settings.validation = validation;
+        self
+    }
+
+    /// Renders on the selected GPU instead of the best-scoring one. The
+    /// `VULKANRENDER_GPU` environment variable, an index or part of a name,
+    /// takes precedence.
+    pub fn device(mut self, selector: DeviceSelector) -> Self {
+        self.device_selector = Some(selector);
+        self
+    }
+
+    /// Optional device features to enable where the GPU supports them.
+    pub fn device_features(mut self, device_features: DeviceFeatures) -> Self {
+        self.device_features = device_features;
+        self
+    }
+
+    pub fn shader_printf(mut self, shader_printf: bool) -> Self {
+        self.debug_settings.shader_printf = shader_printf;
+        self
+    }
+
+    /// Receives validation messages, shader printf output and the renderer's
+    /// own warnings; without it they are dropped.
+    pub fn debug_callback(
+        mut self,
+        callback: impl Fn(&DebugMessage) + Send + Sync + 'static,
+    ) -> Self {
+        self.debug_callback = Some(DebugCallback::new(callback));
+        self
+    }
+
+    /// Present mode to use if the surface supports it; FIFO otherwise.
+    pub fn present_mode(mut self, present_mode: PresentMode) -> Self {
+        self.swapchain_settings.present_mode = present_mode;
+        self
+    }
+
+    pub fn image_count(mut self, image_count: u32) -> Self {
+        self.swapchain_settings.image_count = image_count;
+        self
+    }
+
+    pub fn frames_in_flight(mut self, frames_in_flight: usize) -> Self {
+        self.swapchain_settings.frames_in_flight = frames_in_flight;
+        self
+    }
+
+    pub fn pipeline_settings(mut self, pipeline_settings: PipelineSettings) -> Self {
+        self.pipeline_settings = pipeline_settings;
+        self
+    }
+
+    pub fn clear_color(mut self, clear_color: [f32; 4]) -> Self {
+        self.clear_color = clear_color;
+        self
+    }
+
+    pub fn build(
+        self,
+        window: winit::window::Window,
+    ) -> Result<VulkanRenderer, RendererError> {
+        VulkanRenderer::init(window, self)
+    }
+}
@@ -0,0 +1,62 @@
+use std::ops::{Deref, DerefMut};
+
+/// Holds a Vulkan object that has no `Drop` of its own while the renderer is
+/// assembled, and destroys it with `cleanup` if setup bails out before
+/// [`CleanupGuard::into_inner`] hands it over.
+pub(crate) struct CleanupGuard<T, F: FnOnce(&mut T)> {
+    value: Option<T>,
+    cleanup: Option<F>,
+}
+
+impl<T, F: FnOnce(&mut T)> CleanupGuard<T, F> {
+    pub fn new(value: T, cleanup: F) -> CleanupGuard<T, F> {
+        CleanupGuard {
+            value: Some(value),
+            cleanup: Some(cleanup),
+        }
+    }
+
+    /// Returns the value without cleaning it up.
+    pub fn into_inner(mut guard: CleanupGuard<T, F>) -> T {
+        guard.cleanup = None;
+        guard.value.take().expect("guard is only emptied here")
+    }
+}
+
+impl<T, F: FnOnce(&mut T)> Deref for CleanupGuard<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value.as_ref().expect("guard is only emptied by into_inner")
+    }
+}
+
+impl<T, F: FnOnce(&mut T)> DerefMut for CleanupGuard<T, F> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("guard is only emptied by into_inner")
+    }
+}
+
+impl<T, F: FnOnce(&mut T)> Drop for CleanupGuard<T, F> {
+    fn drop(&mut self) {
+        if let (Some(value), Some(cleanup)) = (self.value.as_mut(), self.cleanup.take()) {
+            cleanup(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn cleanup_runs_unless_the_value_is_handed_over() {
+        let cleaned = Cell::new(0);
+        drop(CleanupGuard::new(1, |value: &mut i32| cleaned.set(*value)));
+        assert_eq!(cleaned.get(), 1);
+        let guard = CleanupGuard::new(2, |value: &mut i32| cleaned.set(*value));
+        assert_eq!(CleanupGuard::into_inner(guard), 2);
+        assert_eq!(cleaned.get(), 1);
+    }
+}
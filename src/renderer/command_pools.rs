@@ -1,49 +1,117 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread::ThreadId;
+
 use ash::vk;
 
 use crate::renderer::device::QueueFamilies;
+use crate::renderer::error::RendererError;
+use crate::renderer::frame_guard::{FrameEpochs, FrameGuarded, StaleFrameResource};
+
+struct FramePool {
+    pool: vk::CommandPool,
+    commandbuffers: Vec<vk::CommandBuffer>,
+    in_use: usize,
+}
+
+#[derive(Default)]
+struct FramePools {
+    pools: HashMap<(ThreadId, usize), FramePool>,
+    epochs: FrameEpochs,
+}
 
 pub struct CommandPools {
-    commandpool_graphics: vk::CommandPool,
-    commandpool_transfer: vk::CommandPool,
+    graphics_q_index: u32,
+    // one pool per recording thread and frame, created on first use
+    frame_pools: Mutex<FramePools>,
 }
 
 impl CommandPools {
-    pub fn new(
-        logical_device: &ash::Device,
-        queue_families: &QueueFamilies,
-    ) -> Result<CommandPools, vk::Result> {
-        let graphics_commandpool_info = vk::CommandPoolCreateInfo::builder()
-            .queue_family_index(queue_families.graphics_q_index.unwrap())
-            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
-        let commandpool_graphics =
-            unsafe { logical_device.create_command_pool(&graphics_commandpool_info, None) }?;
-        let transfer_commandpool_info = vk::CommandPoolCreateInfo::builder()
-            .queue_family_index(queue_families.transfer_q_index.unwrap())
-            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
-        let commandpool_transfer =
-            unsafe { logical_device.create_command_pool(&transfer_commandpool_info, None) }?;
+    pub fn new(queue_families: &QueueFamilies) -> Result<CommandPools, RendererError> {
+        // Device::new refuses devices without it
+        let graphics_q_index = queue_families
+            .graphics_q_index
+            .ok_or(RendererError::MissingQueueFamily("graphics"))?;
         Ok(CommandPools {
-            commandpool_transfer,
-            commandpool_graphics,
+            graphics_q_index,
+            frame_pools: Mutex::new(FramePools::default()),
         })
     }
 
-    pub fn create_commandbuffers(
+    /// Hands out a primary graphics command buffer owned by the calling
+    /// thread's pool for `frame_index`. The buffer stays valid until
+    /// `reset_frame` is called for that frame; resolve it with
+    /// `commandbuffer` to catch use after that point.
+    pub fn frame_commandbuffer(
+        &self,
+        logical_device: &ash::Device,
+        frame_index: usize,
+    ) -> Result<FrameGuarded<vk::CommandBuffer>, vk::Result> {
+        let mut frame_pools = self.frame_pools.lock().unwrap();
+        let frame_pools = &mut *frame_pools;
+        let key = (std::thread::current().id(), frame_index);
+        let frame_pool = match frame_pools.pools.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let pool_info = vk::CommandPoolCreateInfo::builder()
+                    .queue_family_index(self.graphics_q_index)
+                    .flags(vk::CommandPoolCreateFlags::TRANSIENT);
+                let pool = unsafe { logical_device.create_command_pool(&pool_info, None) }?;
+                entry.insert(FramePool {
+                    pool,
+                    commandbuffers: vec![],
+                    in_use: 0,
+                })
+            }
+        };
+        if frame_pool.in_use == frame_pool.commandbuffers.len() {
+            let commandbuf_allocate_info = vk::CommandBufferAllocateInfo::builder()
+                .command_pool(frame_pool.pool)
+                .command_buffer_count(1);
+            let commandbuffer =
+                unsafe { logical_device.allocate_command_buffers(&commandbuf_allocate_info) }?[0];
+            frame_pool.commandbuffers.push(commandbuffer);
+        }
+        let commandbuffer = frame_pool.commandbuffers[frame_pool.in_use];
+        frame_pool.in_use += 1;
+        Ok(frame_pools.epochs.guard(frame_index, commandbuffer))
+    }
+
+    pub fn commandbuffer(
+        &self,
+        guarded: &FrameGuarded<vk::CommandBuffer>,
+    ) -> Result<vk::CommandBuffer, StaleFrameResource> {
+        guarded.get(&self.frame_pools.lock().unwrap().epochs)
+    }
+
+    /// Resets every thread's pool for `frame_index`. Must only be called once
+    /// the GPU has finished with that frame, e.g. after waiting on its fence.
+    pub fn reset_frame(
+        &self,
         logical_device: &ash::Device,
-        pools: &CommandPools,
-        amount: usize,
-    ) -> Result<Vec<vk::CommandBuffer>, vk::Result> {
-        let commandbuf_allocate_info = vk::CommandBufferAllocateInfo::builder()
-            .command_pool(pools.commandpool_graphics)
-            .command_buffer_count(amount as u32);
-        unsafe { logical_device.allocate_command_buffers(&commandbuf_allocate_info) }
+        frame_index: usize,
+    ) -> Result<(), vk::Result> {
+        let mut frame_pools = self.frame_pools.lock().unwrap();
+        frame_pools.epochs.retire(frame_index);
+        for ((_, index), frame_pool) in frame_pools.pools.iter_mut() {
+            if *index != frame_index {
+                continue;
+            }
+            unsafe {
+                logical_device
+                    .reset_command_pool(frame_pool.pool, vk::CommandPoolResetFlags::empty())?
+            };
+            frame_pool.in_use = 0;
+        }
+        Ok(())
     }
 
     pub fn cleanup(&self, logical_device: &ash::Device) {
         unsafe {
-            logical_device.destroy_command_pool(self.commandpool_graphics, None);
-            logical_device.destroy_command_pool(self.commandpool_transfer, None);
+            for frame_pool in self.frame_pools.lock().unwrap().pools.values() {
+                logical_device.destroy_command_pool(frame_pool.pool, None);
+            }
         }
     }
 }
-
@@ -1,12 +1,173 @@
+use std::ffi::{CStr, CString};
+use std::sync::Arc;
+
 use ash::vk;
 
+use super::device::has_extension;
+
+pub const VALIDATION_LAYER: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+#[derive(Clone, Copy, Debug)]
+pub struct DebugSettings {
+    /// Enables the Khronos validation layer and the debug messenger. On by
+    /// default in debug builds.
+    pub validation: bool,
+    /// Enables `debugPrintfEXT` in shaders through the validation layer.
+    /// Printed messages are routed through the debug callback. Requires
+    /// `validation`.
+    pub shader_printf: bool,
+}
+
+impl Default for DebugSettings {
+    fn default() -> Self {
+        DebugSettings {
+            validation: cfg!(debug_assertions),
+            shader_printf: false,
+        }
+    }
+}
+
+/// A debug feature that was asked for but turned off because the layer or
+/// extension it needs is not available.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DisabledFeature {
+    /// `"validation"` or `"shader printf"`.
+    pub feature: &'static str,
+    /// The layer or extension that is missing.
+    pub missing: CString,
+}
+
+impl DisabledFeature {
+    pub(crate) fn new(feature: &'static str, missing: &CStr) -> DisabledFeature {
+        DisabledFeature {
+            feature,
+            missing: missing.to_owned(),
+        }
+    }
+}
+
+impl std::fmt::Display for DisabledFeature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} is not available, {} is off",
+            self.missing.to_string_lossy(),
+            self.feature,
+        )
+    }
+}
+
+impl DebugSettings {
+    /// Turns off validation or shader printf if the layer or extensions they
+    /// need are not installed, e.g. on machines without the Vulkan SDK, and
+    /// returns what was turned off.
+    pub fn disable_unavailable(
+        &mut self,
+        entry: &ash::Entry,
+    ) -> Result<Vec<DisabledFeature>, vk::Result> {
+        if !self.validation {
+            return Ok(vec![]);
+        }
+        let layers = entry.enumerate_instance_layer_properties()?;
+        if !has_layer(&layers, VALIDATION_LAYER) {
+            self.validation = false;
+            return Ok(vec![DisabledFeature::new("validation", VALIDATION_LAYER)]);
+        }
+        let mut extensions = entry.enumerate_instance_extension_properties(None)?;
+        extensions.extend(entry.enumerate_instance_extension_properties(Some(VALIDATION_LAYER))?);
+        let debug_utils = ash::extensions::ext::DebugUtils::name();
+        if !has_extension(&extensions, debug_utils) {
+            self.validation = false;
+            return Ok(vec![DisabledFeature::new("validation", debug_utils)]);
+        }
+        let validation_features = vk::ExtValidationFeaturesFn::name();
+        if self.shader_printf && !has_extension(&extensions, validation_features) {
+            self.shader_printf = false;
+            return Ok(vec![DisabledFeature::new("shader printf", validation_features)]);
+        }
+        Ok(vec![])
+    }
+}
+
+/// A message from the validation layer or the renderer, passed to the
+/// application's [`DebugCallback`].
+#[derive(Clone, Copy, Debug)]
+pub enum DebugMessage<'a> {
+    /// Text a shader printed with `debugPrintfEXT`.
+    Shader(&'a str),
+    /// A problem the renderer worked around, e.g. a device it could not
+    /// query.
+    Renderer(&'a str),
+    Validation {
+        severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+        ty: vk::DebugUtilsMessageTypeFlagsEXT,
+        message: &'a str,
+    },
+}
+
+impl std::fmt::Display for DebugMessage<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DebugMessage::Shader(printed) => write!(f, "[Shader] {}", printed),
+            DebugMessage::Renderer(message) => write!(f, "[Renderer] {}", message),
+            DebugMessage::Validation { severity, ty, message } => {
+                let severity = format!("{:?}", severity).to_lowercase();
+                let ty = format!("{:?}", ty).to_lowercase();
+                write!(f, "[Debug][{}][{}] {}", severity, ty, message)
+            }
+        }
+    }
+}
+
+/// Receives the validation layer's and the renderer's messages; Vulkan may
+/// call it from any thread. Without one, the messages are dropped.
+#[derive(Clone)]
+pub struct DebugCallback(Arc<dyn Fn(&DebugMessage) + Send + Sync>);
+
+impl DebugCallback {
+    pub fn new(callback: impl Fn(&DebugMessage) + Send + Sync + 'static) -> DebugCallback {
+        DebugCallback(Arc::new(callback))
+    }
+
+    pub(crate) fn call(&self, message: &DebugMessage) {
+        (self.0)(message)
+    }
+}
+
+impl std::fmt::Debug for DebugCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DebugCallback")
+    }
+}
+
+/// Whether `name` is among the layer properties Vulkan reported.
+pub fn has_layer(available: &[vk::LayerProperties], name: &CStr) -> bool {
+    available.iter().any(|properties| {
+        let layer_name = unsafe { CStr::from_ptr(properties.layer_name.as_ptr()) };
+        layer_name == name
+    })
+}
+
 pub struct Debug {
     loader: ash::extensions::ext::DebugUtils,
     messenger: vk::DebugUtilsMessengerEXT,
+    // boxed so the pointer handed to Vulkan stays valid when `Debug` moves;
+    // freed after the messenger is destroyed
+    _callback: Option<Box<DebugCallback>>,
 }
 
 impl Debug {
-    pub fn new(entry: &ash::Entry, instance: &ash::Instance) -> Result<Debug, vk::Result> {
+    pub fn new(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        callback: Option<DebugCallback>,
+    ) -> Result<Debug, vk::Result> {
+        let callback = callback.map(Box::new);
+        let user_data = callback
+            .as_deref()
+            .map_or(std::ptr::null_mut(), |callback| {
+                callback as *const DebugCallback as *mut std::ffi::c_void
+            });
         let debugcreateinfo = vk::DebugUtilsMessengerCreateInfoEXT::builder()
             .message_severity(
                 vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
@@ -19,14 +180,19 @@ impl Debug {
                 | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
                 | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
             )
-            .pfn_user_callback(Some(vulkan_debug_utils_callback));
+            .pfn_user_callback(Some(vulkan_debug_utils_callback))
+            .user_data(user_data);
 
         let loader = ash::extensions::ext::DebugUtils::new(entry, instance);
         let messenger = unsafe {
             loader.create_debug_utils_messenger(&debugcreateinfo, None)?
         };
 
-        Ok(Debug { loader, messenger })
+        Ok(Debug {
+            loader,
+            messenger,
+            _callback: callback,
+        })
     }
 }
 
@@ -43,11 +209,27 @@ unsafe extern "system" fn vulkan_debug_utils_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _p_user_data: *mut std::ffi::c_void,
+    p_user_data: *mut std::ffi::c_void,
 ) -> vk::Bool32 {
-    let message = std::ffi::CStr::from_ptr((*p_callback_data).p_message);
-    let severity = format!("{:?}", message_severity).to_lowercase();
-    let ty = format!("{:?}", message_type).to_lowercase();
-    println!("[Debug][{}][{}] {:?}", severity, ty, message);
+    if p_user_data.is_null() {
+        return vk::FALSE;
+    }
+    let callback = &*(p_user_data as *const DebugCallback);
+    let message = std::ffi::CStr::from_ptr((*p_callback_data).p_message).to_string_lossy();
+    let p_message_id_name = (*p_callback_data).p_message_id_name;
+    if !p_message_id_name.is_null() {
+        let message_id_name = std::ffi::CStr::from_ptr(p_message_id_name).to_string_lossy();
+        if message_id_name.contains("DEBUG-PRINTF") {
+            // the printed text is the last `|`-separated part of the message
+            let printed = message.rsplit('|').next().unwrap_or_default().trim();
+            callback.call(&DebugMessage::Shader(printed));
+            return vk::FALSE;
+        }
+    }
+    callback.call(&DebugMessage::Validation {
+        severity: message_severity,
+        ty: message_type,
+        message: &message,
+    });
     vk::FALSE
 }
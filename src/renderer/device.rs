@@ -1,109 +1,832 @@
+use std::sync::{Mutex, PoisonError};
+
 use ash::vk;
 
+use super::cleanup_guard::CleanupGuard;
+use super::debug::{DebugCallback, DebugMessage, DebugSettings, DisabledFeature};
+use super::error::RendererError;
+use super::surface::Surface;
+
 pub struct Queues {
     pub graphics_queue: vk::Queue,
     pub transfer_queue: vk::Queue,
+    /// Same queue as `graphics_queue` unless the graphics family can not
+    /// present.
+    pub present_queue: vk::Queue,
+}
+
+/// The queue one-off work from `Device::immediate_submit` runs on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueueKind {
+    Graphics,
+    Transfer,
 }
 
 pub struct QueueFamilies {
     pub graphics_q_index: Option<u32>,
     pub transfer_q_index: Option<u32>,
+    pub present_q_index: Option<u32>,
 }
 
 impl QueueFamilies {
     pub fn new(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
+        surfaces: &Surface,
     ) -> Result<QueueFamilies, vk::Result> {
         let queuefamilyproperties = unsafe {
             instance.get_physical_device_queue_family_properties(physical_device)
         };
+        let mut queue_families = Self::from_properties(&queuefamilyproperties);
+        let mut present_support = Vec::with_capacity(queuefamilyproperties.len());
+        for index in 0..queuefamilyproperties.len() as u32 {
+            present_support.push(surfaces.supports_present(physical_device, index)?);
+        }
+        queue_families.present_q_index =
+            choose_present_family(queue_families.graphics_q_index, &present_support);
+        Ok(queue_families)
+    }
+
+    /// Whether graphics and presentation happen in different families, so
+    /// swapchain images have to be shared between them.
+    pub fn separate_present_family(&self) -> bool {
+        self.present_q_index != self.graphics_q_index
+    }
+
+    /// Whether transfers happen outside the graphics family, so buffers
+    /// filled on the transfer queue have to be shared with it.
+    pub fn separate_transfer_family(&self) -> bool {
+        self.transfer_q_index != self.graphics_q_index
+    }
+
+    /// Picks the first graphics family and the most specialised family that
+    /// can do transfers: a dedicated transfer family if there is one, then any
+    /// family without graphics (e.g. async compute), then the graphics family.
+    pub fn from_properties(queuefamilyproperties: &[vk::QueueFamilyProperties]) -> QueueFamilies {
         let mut found_graphics_q_index = None;
         let mut found_transfer_q_index = None;
+        let mut found_transfer_rank = 0;
         for (index, qfam) in queuefamilyproperties.iter().enumerate() {
-            if qfam.queue_count > 0 
-                && qfam.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
+            if qfam.queue_count == 0 {
+                continue;
+            }
+            let flags = qfam.queue_flags;
+            if flags.contains(vk::QueueFlags::GRAPHICS) && found_graphics_q_index.is_none() {
                 found_graphics_q_index = Some(index as u32);
             }
-            if qfam.queue_count > 0 && qfam.queue_flags.contains(vk::QueueFlags::TRANSFER) {
-                if found_transfer_q_index.is_none() 
-                    || !qfam.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-                        found_transfer_q_index = Some(index as u32);
-                    }
+            // graphics and compute families support transfers implicitly
+            let supports_transfer = flags.intersects(
+                vk::QueueFlags::TRANSFER | vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE,
+            );
+            if !supports_transfer {
+                continue;
+            }
+            let transfer_rank = if !flags.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE) {
+                3
+            } else if !flags.contains(vk::QueueFlags::GRAPHICS) {
+                2
+            } else {
+                1
+            };
+            if transfer_rank > found_transfer_rank {
+                found_transfer_q_index = Some(index as u32);
+                found_transfer_rank = transfer_rank;
             }
         }
-        Ok(QueueFamilies{
+        QueueFamilies {
             graphics_q_index: found_graphics_q_index,
             transfer_q_index: found_transfer_q_index,
+            present_q_index: None,
+        }
+    }
+
+    pub fn unique_indices(&self) -> Vec<u32> {
+        let mut indices: Vec<u32> = [self.graphics_q_index, self.transfer_q_index, self.present_q_index]
+            .into_iter()
+            .flatten()
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    }
+}
+
+/// Presents from the graphics family when it can, so no image has to change
+/// families; otherwise from the first family that supports the surface.
+pub fn choose_present_family(graphics_q_index: Option<u32>, present_support: &[bool]) -> Option<u32> {
+    let supports = |index: u32| present_support.get(index as usize).copied().unwrap_or(false);
+    match graphics_q_index {
+        Some(graphics) if supports(graphics) => Some(graphics),
+        _ => (0..present_support.len() as u32).find(|&index| supports(index)),
+    }
+}
+
+/// A physical device as seen when choosing which one to render with.
+#[derive(Clone, Debug)]
+pub struct PhysicalDeviceInfo {
+    pub physical_device: vk::PhysicalDevice,
+    /// Position in the order Vulkan enumerates the devices.
+    pub index: usize,
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    /// Total size of the device-local memory heaps in bytes.
+    pub device_local_memory: u64,
+    /// The optional features the device supports.
+    pub supported_features: DeviceFeatures,
+    /// Higher is better; `None` if the device lacks a graphics queue or the
+    /// swapchain extension, no queue can present to the surface, or the
+    /// device could not be queried.
+    pub score: Option<u64>,
+}
+
+impl PhysicalDeviceInfo {
+    /// Describes the device; a device whose queues, extensions or surface
+    /// formats can not be queried is reported to `debug_callback` and counts
+    /// as unsuitable, so it does not stop the others from being chosen.
+    fn query(
+        instance: &ash::Instance,
+        surfaces: &Surface,
+        physical_device: vk::PhysicalDevice,
+        index: usize,
+        debug_callback: Option<&DebugCallback>,
+    ) -> PhysicalDeviceInfo {
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let device_local_memory = memory_properties.memory_heaps
+            [..memory_properties.memory_heap_count as usize]
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum();
+        let supported_features = DeviceFeatures::ALL
+            .supported_subset(&unsafe { instance.get_physical_device_features(physical_device) });
+        let name = unsafe { std::ffi::CStr::from_ptr(properties.device_name.as_ptr()) }
+            .to_string_lossy()
+            .into_owned();
+        let suitable = match Self::can_render(instance, surfaces, physical_device) {
+            Ok(suitable) => suitable,
+            Err(err) => {
+                if let Some(callback) = debug_callback {
+                    let message = format!("skipping device {} ({}): {}", index, name, err);
+                    callback.call(&DebugMessage::Renderer(&message));
+                }
+                false
+            }
+        };
+        PhysicalDeviceInfo {
+            physical_device,
+            index,
+            name,
+            device_type: properties.device_type,
+            device_local_memory,
+            supported_features,
+            score: score_device(
+                properties.device_type,
+                device_local_memory,
+                supported_features,
+                suitable,
+            ),
+        }
+    }
+
+    fn can_render(
+        instance: &ash::Instance,
+        surfaces: &Surface,
+        physical_device: vk::PhysicalDevice,
+    ) -> Result<bool, vk::Result> {
+        let queue_families = QueueFamilies::new(instance, physical_device, surfaces)?;
+        let extensions =
+            unsafe { instance.enumerate_device_extension_properties(physical_device) }?;
+        Ok(queue_families.graphics_q_index.is_some()
+            && queue_families.present_q_index.is_some()
+            && has_extension(&extensions, ash::extensions::khr::Swapchain::name())
+            && !surfaces.get_formats(physical_device)?.is_empty())
+    }
+}
+
+/// Optional device features. Only the ones the device supports are enabled;
+/// `Device::enabled_features` records which.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeviceFeatures {
+    /// Anisotropic texture filtering in samplers.
+    pub sampler_anisotropy: bool,
+    /// Line and point polygon modes, e.g. for wireframes.
+    pub fill_mode_non_solid: bool,
+    /// Line widths other than 1.0.
+    pub wide_lines: bool,
+    /// A non-zero `DepthBias::clamp`.
+    pub depth_bias_clamp: bool,
+}
+
+impl DeviceFeatures {
+    /// Every optional feature the renderer knows about.
+    pub const ALL: DeviceFeatures = DeviceFeatures {
+        sampler_anisotropy: true,
+        fill_mode_non_solid: true,
+        wide_lines: true,
+        depth_bias_clamp: true,
+    };
+
+    /// How many of the features are on.
+    pub fn count(self) -> u64 {
+        [
+            self.sampler_anisotropy,
+            self.fill_mode_non_solid,
+            self.wide_lines,
+            self.depth_bias_clamp,
+        ]
+            .into_iter()
+            .filter(|&on| on)
+            .count() as u64
+    }
+
+    /// The requested features that `supported` has.
+    pub fn supported_subset(self, supported: &vk::PhysicalDeviceFeatures) -> DeviceFeatures {
+        DeviceFeatures {
+            sampler_anisotropy: self.sampler_anisotropy && supported.sampler_anisotropy == vk::TRUE,
+            fill_mode_non_solid: self.fill_mode_non_solid
+                && supported.fill_mode_non_solid == vk::TRUE,
+            wide_lines: self.wide_lines && supported.wide_lines == vk::TRUE,
+            depth_bias_clamp: self.depth_bias_clamp && supported.depth_bias_clamp == vk::TRUE,
+        }
+    }
+
+    pub fn to_vk(self) -> vk::PhysicalDeviceFeatures {
+        vk::PhysicalDeviceFeatures {
+            sampler_anisotropy: self.sampler_anisotropy.into(),
+            fill_mode_non_solid: self.fill_mode_non_solid.into(),
+            wide_lines: self.wide_lines.into(),
+            depth_bias_clamp: self.depth_bias_clamp.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Forces a particular physical device instead of the best-scoring one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelector {
+    /// Position in the list returned by `Device::enumerate`.
+    Index(usize),
+    /// First device whose name contains this, ignoring case.
+    Name(String),
+}
+
+impl DeviceSelector {
+    /// Environment variable that overrides the selector set in the builder.
+    pub const ENV_VAR: &'static str = "VULKANRENDER_GPU";
+
+    /// Reads `ENV_VAR`, if set.
+    pub fn from_env() -> Option<DeviceSelector> {
+        let value = std::env::var(Self::ENV_VAR).ok()?;
+        let value = value.trim();
+        if value.is_empty() {
+            None
+        } else {
+            Some(Self::parse(value))
+        }
+    }
+
+    /// A number selects by index, anything else by name.
+    pub fn parse(value: &str) -> DeviceSelector {
+        match value.parse() {
+            Ok(index) => DeviceSelector::Index(index),
+            Err(_) => DeviceSelector::Name(value.to_owned()),
+        }
+    }
+
+    pub fn matches(&self, device: &PhysicalDeviceInfo) -> bool {
+        match self {
+            DeviceSelector::Index(index) => device.index == *index,
+            DeviceSelector::Name(name) => {
+                device.name.to_lowercase().contains(&name.to_lowercase())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceSelector::Index(index) => write!(f, "device index {}", index),
+            DeviceSelector::Name(name) => write!(f, "device name \"{}\"", name),
+        }
+    }
+}
+
+/// Picks the best-scoring suitable device `selector` matches, or the best
+/// of all without one. A selector that matches no suitable device is
+/// reported as `NoMatchingDevice`, so users see their override was the cause.
+pub fn choose_device(
+    devices: Vec<PhysicalDeviceInfo>,
+    selector: Option<&DeviceSelector>,
+) -> Result<PhysicalDeviceInfo, RendererError> {
+    // the first enumerated device wins a tie
+    devices
+        .into_iter()
+        .filter(|device| device.score.is_some())
+        .filter(|device| selector.is_none_or(|selector| selector.matches(device)))
+        .max_by_key(|device| (device.score, std::cmp::Reverse(device.index)))
+        .ok_or_else(|| match selector {
+            Some(selector) => RendererError::NoMatchingDevice(selector.clone()),
+            None => RendererError::NoSuitableDevice,
         })
+}
+
+/// Ranks discrete over integrated over virtual GPUs over CPU implementations,
+/// then more device-local memory within the same type, then more supported
+/// optional features.
+pub fn score_device(
+    device_type: vk::PhysicalDeviceType,
+    device_local_memory: u64,
+    supported_features: DeviceFeatures,
+    suitable: bool,
+) -> Option<u64> {
+    if !suitable {
+        return None;
     }
+    let type_rank = match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+        vk::PhysicalDeviceType::CPU => 1,
+        _ => 0,
+    };
+    const MIB: u64 = 1024 * 1024;
+    // a million MiB of memory can not outweigh a better device type, and
+    // features only break ties between otherwise equal devices
+    let rank = type_rank * 1_000_000 + (device_local_memory / MIB).min(999_999);
+    Some(rank * (DeviceFeatures::ALL.count() + 1) + supported_features.count())
 }
 
 pub struct Device {
     pub physical_device: vk::PhysicalDevice,
+    /// The device that was picked and why.
+    pub info: PhysicalDeviceInfo,
+    /// The requested features the device supported, which are the ones on.
+    pub enabled_features: DeviceFeatures,
+    /// Debug features turned off because the device lacks an extension.
+    pub disabled_features: Vec<DisabledFeature>,
     pub logical_device: ash::Device,
     pub queue_families: QueueFamilies,
     pub queues: Queues,
+    // transient pools for immediate_submit, one per queue kind; locked while
+    // in use, since a pool must not be used from two threads at once
+    immediate_graphics_pool: Mutex<vk::CommandPool>,
+    immediate_transfer_pool: Mutex<vk::CommandPool>,
 }
 
 impl Device {
+    /// Creates the logical device on the physical device `info` describes,
+    /// usually the one `select` picked.
     pub fn new(
         instance: &ash::Instance,
-        layer_name_pointers: &Vec<*const i8>,
-    ) -> Result<Device, vk::Result> {
-        let physical_device = Self::get_physical_device(instance)?;
-        let queue_families = QueueFamilies::new(instance, physical_device)?;
+        surfaces: &Surface,
+        info: PhysicalDeviceInfo,
+        layer_name_pointers: &[*const i8],
+        debug_settings: &DebugSettings,
+        features: &DeviceFeatures,
+        extra_extensions: &[std::ffi::CString],
+    ) -> Result<Device, RendererError> {
+        let physical_device = info.physical_device;
+        let queue_families = QueueFamilies::new(instance, physical_device, surfaces)
+            .map_err(RendererError::Device)?;
+        let graphics_q_index = queue_families
+            .graphics_q_index
+            .ok_or(RendererError::MissingQueueFamily("graphics"))?;
+        let transfer_q_index = queue_families
+            .transfer_q_index
+            .ok_or(RendererError::MissingQueueFamily("transfer"))?;
+        let present_q_index = queue_families
+            .present_q_index
+            .ok_or(RendererError::MissingQueueFamily("present"))?;
         let priorities = [1.0f32];
-        let queue_infos = [
-            vk::DeviceQueueCreateInfo::builder()
-                .queue_family_index(queue_families.graphics_q_index.unwrap())
-                .queue_priorities(&priorities)
-                .build(),
-            // TODO: Transfer and graphics queue are the same, so are the indices (0, 0), throws error
-            // vk::DeviceQueueCreateInfo::builder()
-            //     .queue_family_index(qfamindices.1)
-            //     .queue_priorities(&priorities)
-            //     .build(),
-        ];
-
-        let device_extension_name_pointers: Vec<*const i8> =
-            vec![ash::extensions::khr::Swapchain::name().as_ptr()];
+        let queue_infos: Vec<vk::DeviceQueueCreateInfo> = queue_families
+            .unique_indices()
+            .into_iter()
+            .map(|index| {
+                vk::DeviceQueueCreateInfo::builder()
+                    .queue_family_index(index)
+                    .queue_priorities(&priorities)
+                    .build()
+            })
+            .collect();
+
+        let available_extensions =
+            unsafe { instance.enumerate_device_extension_properties(physical_device) }
+                .map_err(RendererError::Device)?;
+        if let Some(missing) = extra_extensions
+            .iter()
+            .find(|name| !has_extension(&available_extensions, name))
+        {
+            return Err(RendererError::MissingExtension(missing.clone()));
+        }
+        let (mut device_extension_name_pointers, disabled_features) =
+            Self::used_extensions(debug_settings, &available_extensions);
+        device_extension_name_pointers.extend(extra_extensions.iter().map(|name| name.as_ptr()));
+        let supported_features = unsafe { instance.get_physical_device_features(physical_device) };
+        let enabled_features = features.supported_subset(&supported_features);
+        let vk_features = enabled_features.to_vk();
         let device_create_info = vk::DeviceCreateInfo::builder()
             .queue_create_infos(&queue_infos)
+            .enabled_features(&vk_features)
             .enabled_extension_names(&device_extension_name_pointers)
-            .enabled_layer_names(&layer_name_pointers);
+            .enabled_layer_names(layer_name_pointers);
         let logical_device = 
-            unsafe { instance.create_device(physical_device, &device_create_info, None)? };
+            unsafe { instance.create_device(physical_device, &device_create_info, None) }
+                .map_err(RendererError::Device)?;
+        let logical_device = CleanupGuard::new(logical_device, |device: &mut ash::Device| unsafe {
+            device.destroy_device(None)
+        });
+        let immediate_graphics_pool =
+            Self::create_immediate_pool(&logical_device, graphics_q_index)?;
+        let immediate_graphics_pool =
+            CleanupGuard::new(immediate_graphics_pool, |pool: &mut vk::CommandPool| unsafe {
+                logical_device.destroy_command_pool(*pool, None)
+            });
+        let immediate_transfer_pool =
+            Self::create_immediate_pool(&logical_device, transfer_q_index)?;
         let graphics_queue = 
-            unsafe { logical_device.get_device_queue(queue_families.graphics_q_index.unwrap(), 0) };
+            unsafe { logical_device.get_device_queue(graphics_q_index, 0) };
         let transfer_queue = 
-            unsafe { logical_device.get_device_queue(queue_families.transfer_q_index.unwrap(), 0) };
-
+            unsafe { logical_device.get_device_queue(transfer_q_index, 0) };
+        let present_queue = 
+            unsafe { logical_device.get_device_queue(present_q_index, 0) };
+        let immediate_graphics_pool = CleanupGuard::into_inner(immediate_graphics_pool);
         Ok(Device {
             physical_device,
-            logical_device,
+            info,
+            enabled_features,
+            disabled_features,
+            logical_device: CleanupGuard::into_inner(logical_device),
             queue_families,
             queues: Queues {
                 transfer_queue,
                 graphics_queue,
-            }
+                present_queue,
+            },
+            immediate_graphics_pool: Mutex::new(immediate_graphics_pool),
+            immediate_transfer_pool: Mutex::new(immediate_transfer_pool),
         })
     }
 
-    fn get_physical_device(
-        instance: &ash::Instance
-    ) -> Result<vk::PhysicalDevice, vk::Result> {
-        let phys_devs = unsafe { instance.enumerate_physical_devices()? };
-        let mut chosen = None;
-        for p in phys_devs {
-            chosen = Some(p);
-            break;
+    fn create_immediate_pool(
+        logical_device: &ash::Device,
+        queue_family_index: u32,
+    ) -> Result<vk::CommandPool, RendererError> {
+        let commandpool_info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(queue_family_index)
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT);
+        unsafe { logical_device.create_command_pool(&commandpool_info, None) }
+            .map_err(RendererError::CommandBuffer)
+    }
+
+    /// The queue work of `kind` is submitted to.
+    pub fn queue(&self, kind: QueueKind) -> vk::Queue {
+        match kind {
+            QueueKind::Graphics => self.queues.graphics_queue,
+            QueueKind::Transfer => self.queues.transfer_queue,
+        }
+    }
+
+    fn immediate_pool(&self, kind: QueueKind) -> &Mutex<vk::CommandPool> {
+        match kind {
+            QueueKind::Graphics => &self.immediate_graphics_pool,
+            QueueKind::Transfer => &self.immediate_transfer_pool,
+        }
+    }
+
+    /// Records a one-off command buffer with `record`, submits it to the
+    /// queue of `kind` and blocks until the GPU has executed it. The buffer
+    /// comes from a transient pool the device keeps for that queue's family;
+    /// calls from several threads take turns.
+    pub fn immediate_submit<F>(&self, kind: QueueKind, record: F) -> Result<(), vk::Result>
+    where
+        F: FnOnce(&ash::Device, vk::CommandBuffer),
+    {
+        let queue = self.queue(kind);
+        let commandpool_guard =
+            self.immediate_pool(kind).lock().unwrap_or_else(PoisonError::into_inner);
+        let commandpool = *commandpool_guard;
+        let commandbuf_allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(commandpool)
+            .command_buffer_count(1);
+        let commandbuffer =
+            unsafe { self.logical_device.allocate_command_buffers(&commandbuf_allocate_info) }?[0];
+        let fence = match unsafe {
+            self.logical_device.create_fence(&vk::FenceCreateInfo::builder(), None)
+        } {
+            Ok(fence) => fence,
+            Err(err) => {
+                unsafe {
+                    self.logical_device
+                        .free_command_buffers(commandpool, &[commandbuffer]);
+                }
+                return Err(err);
+            }
+        };
+        let result = self.record_and_wait(queue, commandbuffer, fence, record);
+        unsafe {
+            self.logical_device.destroy_fence(fence, None);
+            self.logical_device
+                .free_command_buffers(commandpool, &[commandbuffer]);
+        }
+        result
+    }
+
+    fn record_and_wait<F>(
+        &self,
+        queue: vk::Queue,
+        commandbuffer: vk::CommandBuffer,
+        fence: vk::Fence,
+        record: F,
+    ) -> Result<(), vk::Result>
+    where
+        F: FnOnce(&ash::Device, vk::CommandBuffer),
+    {
+        let begininfo = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe { self.logical_device.begin_command_buffer(commandbuffer, &begininfo) }?;
+        record(&self.logical_device, commandbuffer);
+        unsafe { self.logical_device.end_command_buffer(commandbuffer) }?;
+        let commandbuffers = [commandbuffer];
+        let submit_info = [vk::SubmitInfo::builder()
+            .command_buffers(&commandbuffers)
+            .build()];
+        unsafe {
+            self.logical_device
+                .queue_submit(queue, &submit_info, fence)?;
+            self.logical_device.wait_for_fences(&[fence], true, u64::MAX)
+        }
+    }
+
+    fn used_extensions(
+        debug_settings: &DebugSettings,
+        available: &[vk::ExtensionProperties],
+    ) -> (Vec<*const i8>, Vec<DisabledFeature>) {
+        let mut extensions = vec![ash::extensions::khr::Swapchain::name().as_ptr()];
+        let mut disabled = vec![];
+        if debug_settings.validation && debug_settings.shader_printf {
+            let non_semantic_info = vk::KhrShaderNonSemanticInfoFn::name();
+            if has_extension(available, non_semantic_info) {
+                extensions.push(non_semantic_info.as_ptr());
+            } else {
+                disabled.push(DisabledFeature::new("shader printf", non_semantic_info));
+            }
         }
-        Ok(chosen.unwrap())
+        // non-conformant implementations like MoltenVK require it to be enabled
+        if has_extension(available, vk::KhrPortabilitySubsetFn::name()) {
+            extensions.push(vk::KhrPortabilitySubsetFn::name().as_ptr());
+        }
+        (extensions, disabled)
+    }
+
+    /// Lists every physical device with its score for rendering to `surfaces`.
+    /// Devices that can not be queried are reported to `debug_callback`.
+    pub fn enumerate(
+        instance: &ash::Instance,
+        surfaces: &Surface,
+        debug_callback: Option<&DebugCallback>,
+    ) -> Result<Vec<PhysicalDeviceInfo>, vk::Result> {
+        let phys_devs = unsafe { instance.enumerate_physical_devices() }?;
+        Ok(phys_devs
+            .into_iter()
+            .enumerate()
+            .map(|(index, physical_device)| {
+                PhysicalDeviceInfo::query(
+                    instance,
+                    surfaces,
+                    physical_device,
+                    index,
+                    debug_callback,
+                )
+            })
+            .collect())
     }
 
+    /// The device `selector` picks, or the best-scoring one without it.
+    pub fn select(
+        instance: &ash::Instance,
+        surfaces: &Surface,
+        selector: Option<&DeviceSelector>,
+        debug_callback: Option<&DebugCallback>,
+    ) -> Result<PhysicalDeviceInfo, RendererError> {
+        let devices =
+            Self::enumerate(instance, surfaces, debug_callback).map_err(RendererError::Device)?;
+        choose_device(devices, selector)
+    }
+
+    /// Destroys the pools of `immediate_submit` and the logical device.
+    ///
+    /// # Safety
+    ///
+    /// The device has to be idle and every other object created from it
+    /// destroyed.
     pub unsafe fn cleanup(&self) {
+        for pool in [&self.immediate_graphics_pool, &self.immediate_transfer_pool] {
+            let pool = *pool.lock().unwrap_or_else(PoisonError::into_inner);
+            self.logical_device.destroy_command_pool(pool, None);
+        }
         self.logical_device.destroy_device(None);
     }
 }
 
+
+/// Whether `name` is among the extension properties Vulkan reported.
+pub fn has_extension(available: &[vk::ExtensionProperties], name: &std::ffi::CStr) -> bool {
+    available.iter().any(|properties| {
+        let extension_name =
+            unsafe { std::ffi::CStr::from_ptr(properties.extension_name.as_ptr()) };
+        extension_name == name
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn family(queue_flags: vk::QueueFlags) -> vk::QueueFamilyProperties {
+        vk::QueueFamilyProperties {
+            queue_flags,
+            queue_count: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn discrete_gpu_beats_integrated_gpu_with_more_memory() {
+        let none = DeviceFeatures::default();
+        let all = DeviceFeatures::ALL;
+        let discrete = score_device(vk::PhysicalDeviceType::DISCRETE_GPU, 4 << 30, none, true);
+        let integrated = score_device(vk::PhysicalDeviceType::INTEGRATED_GPU, 16 << 30, all, true);
+        assert!(discrete > integrated);
+        let smaller = score_device(vk::PhysicalDeviceType::DISCRETE_GPU, 2 << 30, all, true);
+        assert!(discrete > smaller);
+        // equal type and memory: the one with more optional features wins
+        let featured = score_device(vk::PhysicalDeviceType::DISCRETE_GPU, 4 << 30, all, true);
+        let anisotropy_only = DeviceFeatures {
+            sampler_anisotropy: true,
+            ..DeviceFeatures::default()
+        };
+        let partial =
+            score_device(vk::PhysicalDeviceType::DISCRETE_GPU, 4 << 30, anisotropy_only, true);
+        assert!(featured > partial);
+        assert!(partial > discrete);
+    }
+
+    #[test]
+    fn selector_matches_by_index_or_name() {
+        let device = PhysicalDeviceInfo {
+            physical_device: vk::PhysicalDevice::null(),
+            index: 1,
+            name: "NVIDIA GeForce RTX 3070".to_owned(),
+            device_type: vk::PhysicalDeviceType::DISCRETE_GPU,
+            device_local_memory: 8 << 30,
+            supported_features: DeviceFeatures::default(),
+            score: None,
+        };
+        assert_eq!(DeviceSelector::parse("1"), DeviceSelector::Index(1));
+        assert!(DeviceSelector::parse("1").matches(&device));
+        assert!(!DeviceSelector::parse("0").matches(&device));
+        assert!(DeviceSelector::parse("geforce").matches(&device));
+        assert!(!DeviceSelector::parse("radeon").matches(&device));
+    }
+
+    fn device_info(index: usize, name: &str, score: Option<u64>) -> PhysicalDeviceInfo {
+        PhysicalDeviceInfo {
+            physical_device: vk::PhysicalDevice::null(),
+            index,
+            name: name.to_owned(),
+            device_type: vk::PhysicalDeviceType::DISCRETE_GPU,
+            device_local_memory: 8 << 30,
+            supported_features: DeviceFeatures::default(),
+            score,
+        }
+    }
+
+    #[test]
+    fn selector_without_suitable_match_is_reported() {
+        let devices = vec![device_info(0, "Radeon", Some(1)), device_info(1, "GeForce", None)];
+        let chosen = choose_device(devices.clone(), None).unwrap();
+        assert_eq!(chosen.index, 0);
+        let unsuitable = DeviceSelector::parse("geforce");
+        assert!(matches!(
+            choose_device(devices.clone(), Some(&unsuitable)),
+            Err(RendererError::NoMatchingDevice(selector)) if selector == unsuitable
+        ));
+        let missing = DeviceSelector::Index(5);
+        assert!(matches!(
+            choose_device(devices, Some(&missing)),
+            Err(RendererError::NoMatchingDevice(_))
+        ));
+        assert!(matches!(
+            choose_device(vec![device_info(0, "llvmpipe", None)], None),
+            Err(RendererError::NoSuitableDevice)
+        ));
+    }
+
+    #[test]
+    fn unsuitable_device_has_no_score() {
+        let all = DeviceFeatures::ALL;
+        assert_eq!(score_device(vk::PhysicalDeviceType::DISCRETE_GPU, 8 << 30, all, false), None);
+    }
+
+    #[test]
+    fn only_supported_features_are_enabled() {
+        let requested = DeviceFeatures {
+            sampler_anisotropy: true,
+            fill_mode_non_solid: true,
+            wide_lines: false,
+            depth_bias_clamp: true,
+        };
+        let supported = vk::PhysicalDeviceFeatures {
+            sampler_anisotropy: vk::TRUE,
+            wide_lines: vk::TRUE,
+            depth_bias_clamp: vk::TRUE,
+            ..Default::default()
+        };
+        let enabled = requested.supported_subset(&supported);
+        assert_eq!(
+            enabled,
+            DeviceFeatures {
+                sampler_anisotropy: true,
+                fill_mode_non_solid: false,
+                wide_lines: false,
+                depth_bias_clamp: true,
+            },
+        );
+        assert_eq!(enabled.to_vk().sampler_anisotropy, vk::TRUE);
+        assert_eq!(enabled.to_vk().wide_lines, vk::FALSE);
+    }
+
+    #[test]
+    fn present_prefers_the_graphics_family() {
+        assert_eq!(choose_present_family(Some(1), &[true, true]), Some(1));
+        assert_eq!(choose_present_family(Some(1), &[false, false, true]), Some(2));
+        assert_eq!(choose_present_family(Some(0), &[false, false]), None);
+        assert_eq!(choose_present_family(None, &[false, true]), Some(1));
+    }
+
+    #[test]
+    fn single_universal_family_is_used_for_everything() {
+        let families = QueueFamilies::from_properties(&[family(
+            vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER,
+        )]);
+        assert_eq!(families.graphics_q_index, Some(0));
+        assert_eq!(families.transfer_q_index, Some(0));
+        assert_eq!(families.unique_indices(), vec![0]);
+    }
+
+    #[test]
+    fn dedicated_transfer_family_is_preferred() {
+        let families = QueueFamilies::from_properties(&[
+            family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+            family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+            family(vk::QueueFlags::TRANSFER | vk::QueueFlags::SPARSE_BINDING),
+        ]);
+        assert_eq!(families.graphics_q_index, Some(0));
+        assert_eq!(families.transfer_q_index, Some(2));
+        assert_eq!(families.unique_indices(), vec![0, 2]);
+    }
+
+    #[test]
+    fn compute_only_family_is_used_without_dedicated_transfer() {
+        // TRANSFER is implied for compute families even when not reported
+        let families = QueueFamilies::from_properties(&[
+            family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE),
+            family(vk::QueueFlags::COMPUTE),
+        ]);
+        assert_eq!(families.graphics_q_index, Some(0));
+        assert_eq!(families.transfer_q_index, Some(1));
+    }
+
+    #[test]
+    fn graphics_family_does_not_have_to_come_first() {
+        let families = QueueFamilies::from_properties(&[
+            family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+            family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+            family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::TRANSFER),
+        ]);
+        assert_eq!(families.graphics_q_index, Some(1));
+        assert_eq!(families.transfer_q_index, Some(0));
+    }
+
+    #[test]
+    fn empty_families_are_skipped() {
+        let mut empty = family(vk::QueueFlags::TRANSFER);
+        empty.queue_count = 0;
+        let families = QueueFamilies::from_properties(&[
+            family(vk::QueueFlags::GRAPHICS),
+            empty,
+        ]);
+        assert_eq!(families.graphics_q_index, Some(0));
+        assert_eq!(families.transfer_q_index, Some(0));
+    }
+
+    #[test]
+    fn no_graphics_family_is_reported() {
+        let families = QueueFamilies::from_properties(&[family(vk::QueueFlags::COMPUTE)]);
+        assert_eq!(families.graphics_q_index, None);
+        assert_eq!(families.transfer_q_index, Some(0));
+    }
+}
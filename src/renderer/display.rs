@@ -0,0 +1,86 @@
+use winit::dpi::{LogicalSize, PhysicalSize};
+use winit::monitor::VideoMode;
+use winit::window::{Fullscreen, Window};
+
+/// Size of the window's drawable area in pixels and in DPI-independent
+/// units; cameras should use `physical`, UI layout `logical`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SurfaceSize {
+    pub physical: PhysicalSize<u32>,
+    pub logical: LogicalSize<f64>,
+    pub scale_factor: f64,
+}
+
+impl SurfaceSize {
+    pub fn new(physical: PhysicalSize<u32>, scale_factor: f64) -> SurfaceSize {
+        SurfaceSize {
+            physical,
+            logical: physical.to_logical(scale_factor),
+            scale_factor,
+        }
+    }
+
+    pub fn of_window(window: &Window) -> SurfaceSize {
+        Self::new(window.inner_size(), window.scale_factor())
+    }
+}
+
+/// Video modes of the monitor the window is currently on, largest and
+/// fastest first.
+pub fn video_modes(window: &Window) -> Vec<VideoMode> {
+    let mut modes: Vec<VideoMode> = window.current_monitor().video_modes().collect();
+    modes.sort_by_key(|mode| {
+        std::cmp::Reverse((
+            mode.size().width,
+            mode.size().height,
+            mode.refresh_rate(),
+            mode.bit_depth(),
+        ))
+    });
+    modes
+}
+
+/// Finds a mode with the given resolution; without a refresh rate the fastest
+/// one is picked.
+pub fn find_video_mode(
+    window: &Window,
+    width: u32,
+    height: u32,
+    refresh_rate: Option<u16>,
+) -> Option<VideoMode> {
+    video_modes(window).into_iter().find(|mode| {
+        mode.size().width == width
+            && mode.size().height == height
+            && refresh_rate.is_none_or(|rate| mode.refresh_rate() == rate)
+    })
+}
+
+/// Switches to exclusive fullscreen with `mode`, or back to windowed mode.
+pub fn set_display_mode(window: &Window, mode: Option<VideoMode>) {
+    window.set_fullscreen(mode.map(Fullscreen::Exclusive));
+}
+
+/// Refresh rate the window is presented at. In exclusive fullscreen this is
+/// the selected mode's rate, otherwise the fastest mode matching the
+/// monitor's current resolution is assumed.
+pub fn refresh_rate(window: &Window) -> Option<u16> {
+    match window.fullscreen() {
+        Some(Fullscreen::Exclusive(mode)) => Some(mode.refresh_rate()),
+        _ => {
+            let monitor = window.current_monitor();
+            let size = monitor.size();
+            monitor
+                .video_modes()
+                .filter(|mode| mode.size() == size)
+                .map(|mode| mode.refresh_rate())
+                .max()
+        }
+    }
+}
+
+/// Time between two vblanks, for pacing frames to the display.
+pub fn frame_interval(window: &Window) -> Option<std::time::Duration> {
+    refresh_rate(window)
+        .filter(|&rate| rate > 0)
+        .map(|rate| std::time::Duration::from_secs_f64(1.0 / rate as f64))
+}
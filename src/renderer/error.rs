@@ -0,0 +1,140 @@
+use ash::vk;
+
+use super::device::DeviceSelector;
+use super::frame_guard::StaleFrameResource;
+
+/// Everything that can go wrong while setting up or driving the renderer.
+#[derive(Debug)]
+pub enum RendererError {
+    /// A name handed to Vulkan contained an interior nul byte.
+    InvalidName(std::ffi::NulError),
+    /// Creating the instance or the debug messenger failed.
+    Instance(vk::Result),
+    /// A requested layer is not installed.
+    MissingLayer(std::ffi::CString),
+    /// A required instance or device extension is not available.
+    MissingExtension(std::ffi::CString),
+    /// The window is not backed by a platform surface this renderer supports.
+    UnsupportedWindow,
+    Surface(vk::Result),
+    /// No physical device can render to the surface: each lacks a graphics
+    /// queue, a queue that can present, the swapchain extension or any
+    /// surface format.
+    NoSuitableDevice,
+    /// The device selector from the builder or `VULKANRENDER_GPU` matches no
+    /// suitable device.
+    NoMatchingDevice(DeviceSelector),
+    Device(vk::Result),
+    /// The device has no queue family for the named kind of work.
+    MissingQueueFamily(&'static str),
+    Swapchain(vk::Result),
+    /// The surface reported no formats to create a swapchain with.
+    NoSurfaceFormat,
+    Pipeline(vk::Result),
+    /// The pipeline settings contradict each other; the text says how.
+    InvalidPipelineSettings(&'static str),
+    Allocation(gpu_allocator::AllocationError),
+    /// Creating or binding a buffer failed.
+    Buffer(vk::Result),
+    /// A buffer was requested with a size of zero bytes.
+    EmptyBuffer,
+    /// Host access to a buffer whose memory is not host-visible.
+    NotHostVisible,
+    /// A write of `len` bytes at `offset` does not fit into a buffer of
+    /// `size` bytes.
+    OutOfBounds { offset: u64, len: u64, size: u64 },
+    CommandBuffer(vk::Result),
+    StaleFrameResource(StaleFrameResource),
+    Submit(vk::Result),
+    Present(vk::Result),
+}
+
+impl std::fmt::Display for RendererError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RendererError::InvalidName(err) => write!(f, "invalid name: {}", err),
+            RendererError::Instance(err) => write!(f, "instance creation failed: {}", err),
+            RendererError::MissingLayer(name) => {
+                write!(f, "layer {} is not available", name.to_string_lossy())
+            }
+            RendererError::MissingExtension(name) => {
+                write!(f, "extension {} is not available", name.to_string_lossy())
+            }
+            RendererError::UnsupportedWindow => write!(f, "the window has no supported surface"),
+            RendererError::Surface(err) => write!(f, "surface creation failed: {}", err),
+            RendererError::NoSuitableDevice => write!(f, "no suitable physical device found"),
+            RendererError::NoMatchingDevice(selector) => {
+                write!(f, "no suitable physical device matches {}", selector)
+            }
+            RendererError::Device(err) => write!(f, "device creation failed: {}", err),
+            RendererError::MissingQueueFamily(kind) => write!(f, "no {} queue family", kind),
+            RendererError::Swapchain(err) => write!(f, "swapchain error: {}", err),
+            RendererError::NoSurfaceFormat => write!(f, "the surface supports no formats"),
+            RendererError::Pipeline(err) => write!(f, "pipeline creation failed: {}", err),
+            RendererError::InvalidPipelineSettings(reason) => {
+                write!(f, "invalid pipeline settings: {}", reason)
+            }
+            RendererError::Allocation(err) => write!(f, "memory allocation failed: {}", err),
+            RendererError::Buffer(err) => write!(f, "buffer error: {}", err),
+            RendererError::EmptyBuffer => write!(f, "buffers can not be empty"),
+            RendererError::NotHostVisible => write!(f, "buffer memory is not host-visible"),
+            RendererError::OutOfBounds { offset, len, size } => write!(
+                f,
+                "writing {} bytes at {} overflows a buffer of {} bytes",
+                len, offset, size,
+            ),
+            RendererError::CommandBuffer(err) => write!(f, "command buffer error: {}", err),
+            RendererError::StaleFrameResource(err) => write!(f, "{}", err),
+            RendererError::Submit(err) => write!(f, "queue submission failed: {}", err),
+            RendererError::Present(err) => write!(f, "presentation failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RendererError::InvalidName(err) => Some(err),
+            RendererError::Allocation(err) => Some(err),
+            RendererError::StaleFrameResource(err) => Some(err),
+            RendererError::UnsupportedWindow
+            | RendererError::NoSuitableDevice
+            | RendererError::NoMatchingDevice(_)
+            | RendererError::MissingLayer(_)
+            | RendererError::MissingExtension(_)
+            | RendererError::MissingQueueFamily(_)
+            | RendererError::NoSurfaceFormat
+            | RendererError::InvalidPipelineSettings(_)
+            | RendererError::EmptyBuffer
+            | RendererError::NotHostVisible
+            | RendererError::OutOfBounds { .. } => None,
+            RendererError::Instance(err)
+            | RendererError::Surface(err)
+            | RendererError::Device(err)
+            | RendererError::Swapchain(err)
+            | RendererError::Pipeline(err)
+            | RendererError::Buffer(err)
+            | RendererError::CommandBuffer(err)
+            | RendererError::Submit(err)
+            | RendererError::Present(err) => Some(err),
+        }
+    }
+}
+
+impl From<std::ffi::NulError> for RendererError {
+    fn from(err: std::ffi::NulError) -> Self {
+        RendererError::InvalidName(err)
+    }
+}
+
+impl From<gpu_allocator::AllocationError> for RendererError {
+    fn from(err: gpu_allocator::AllocationError) -> Self {
+        RendererError::Allocation(err)
+    }
+}
+
+impl From<StaleFrameResource> for RendererError {
+    fn from(err: StaleFrameResource) -> Self {
+        RendererError::StaleFrameResource(err)
+    }
+}
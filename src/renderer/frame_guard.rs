@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaleFrameResource {
+    pub frame_index: usize,
+}
+
+impl std::fmt::Display for StaleFrameResource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "resource of frame {} used after the frame retired", self.frame_index)
+    }
+}
+
+impl std::error::Error for StaleFrameResource {}
+
+/// Counts how often each frame slot has retired, so values handed out for a
+/// frame can tell whether that frame is still the one they belong to.
+#[derive(Default)]
+pub struct FrameEpochs {
+    epochs: HashMap<usize, u64>,
+}
+
+impl FrameEpochs {
+    pub fn current(&self, frame_index: usize) -> u64 {
+        self.epochs.get(&frame_index).copied().unwrap_or(0)
+    }
+
+    pub fn retire(&mut self, frame_index: usize) {
+        *self.epochs.entry(frame_index).or_insert(0) += 1;
+    }
+
+    pub fn guard<T>(&self, frame_index: usize, value: T) -> FrameGuarded<T> {
+        FrameGuarded {
+            value,
+            frame_index,
+            epoch: self.current(frame_index),
+        }
+    }
+}
+
+/// A value that is only valid until its frame retires.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameGuarded<T> {
+    value: T,
+    frame_index: usize,
+    epoch: u64,
+}
+
+impl<T: Copy> FrameGuarded<T> {
+    pub fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    pub fn get(&self, epochs: &FrameEpochs) -> Result<T, StaleFrameResource> {
+        if epochs.current(self.frame_index) == self.epoch {
+            Ok(self.value)
+        } else {
+            Err(StaleFrameResource {
+                frame_index: self.frame_index,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guarded_value_expires_when_its_frame_retires() {
+        let mut epochs = FrameEpochs::default();
+        let first = epochs.guard(0, 7u32);
+        let second = epochs.guard(1, 8u32);
+        assert_eq!(first.get(&epochs), Ok(7));
+        epochs.retire(0);
+        assert_eq!(first.get(&epochs), Err(StaleFrameResource { frame_index: 0 }));
+        assert_eq!(second.get(&epochs), Ok(8));
+        assert_eq!(epochs.guard(0, 9u32).get(&epochs), Ok(9));
+    }
+}
@@ -0,0 +1,62 @@
+use ash::vk;
+
+/// Where in a frame's command buffer a hook is recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InjectionPoint {
+    /// Before the main render pass begins, e.g. for compute work or copies.
+    BeforeMainPass,
+    /// Inside the main render pass, after the renderer's own meshes.
+    MainPass,
+    /// After the main render pass has ended. The swapchain image is already
+    /// in `PRESENT_SRC_KHR` layout here.
+    AfterMainPass,
+}
+
+/// What a hook gets to record with.
+pub struct HookContext<'a> {
+    pub logical_device: &'a ash::Device,
+    pub commandbuffer: vk::CommandBuffer,
+    pub framebuffer: vk::Framebuffer,
+    pub extent: vk::Extent2D,
+    pub image_index: u32,
+    pub frame_index: usize,
+}
+
+/// `Send` so the renderer that owns the hooks can move to a render thread.
+pub type RenderHook = Box<dyn FnMut(&HookContext) + Send>;
+
+/// Application callbacks recorded into every frame at their injection point,
+/// in the order they were added.
+#[derive(Default)]
+pub struct RenderHooks {
+    hooks: Vec<(InjectionPoint, RenderHook)>,
+}
+
+impl RenderHooks {
+    pub fn add(&mut self, point: InjectionPoint, hook: RenderHook) {
+        self.hooks.push((point, hook));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    pub fn run(&mut self, point: InjectionPoint, context: &HookContext) {
+        for (_, hook) in self.hooks.iter_mut().filter(|(p, _)| *p == point) {
+            hook(context);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn hooks_keep_the_renderer_send() {
+        assert_send::<RenderHooks>();
+        assert_send::<crate::renderer::VulkanRenderer>();
+    }
+}
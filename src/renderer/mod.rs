@@ -1,120 +1,533 @@
+//! The renderer and the Vulkan objects it is assembled from.
+
 pub mod debug;
 pub mod swapchain;
 pub mod pipeline;
 pub mod surface;
 pub mod command_pools;
 pub mod device;
+pub mod queue_runner;
+pub mod frame_guard;
+pub mod display;
+pub mod window_controller;
+pub mod projection;
+pub mod builder;
+pub mod error;
+pub mod vertex;
+pub mod hooks;
+pub mod staging;
+pub mod buffer;
+mod cleanup_guard;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use ash::vk;
-use debug::Debug;
-use swapchain::Swapchain;
-use pipeline::Pipeline;
+use gpu_allocator::vulkan::{Allocator, AllocatorCreateDesc};
+use debug::{Debug, DebugCallback, DisabledFeature};
+use swapchain::{Acquired, PresentMode, Swapchain, SwapchainStatus};
+use pipeline::{ChannelView, Pipeline};
 use surface::Surface;
 use command_pools::CommandPools;
-use device::Device;
+use device::{Device, DeviceSelector, PhysicalDeviceInfo};
+use display::SurfaceSize;
+use window_controller::WindowController;
+use queue_runner::{QueueRunner, Submission};
+use builder::RendererBuilder;
+use error::RendererError;
+use vertex::{ColoredVertex, VertexBuffer};
+use staging::StagingBuffer;
+use buffer::SharedAllocator;
+use hooks::{HookContext, InjectionPoint, RenderHook, RenderHooks};
+use cleanup_guard::CleanupGuard;
 
+/// Owns the window and every Vulkan object needed to draw into it.
 pub struct VulkanRenderer {
-    pub window: winit::window::Window,
+    pub window: WindowController,
     pub entry: ash::Entry,
     pub instance: ash::Instance,
-    pub debug: std::mem::ManuallyDrop<Debug>,
+    pub debug: Option<Debug>,
     pub surfaces: std::mem::ManuallyDrop<Surface>,
     pub device: Device,
     pub swapchain: Swapchain,
     pub renderpass: vk::RenderPass,
     pub pipeline: Pipeline,
+    /// Device memory allocator for buffers; freed before the device, so
+    /// buffers and clones of it must not outlive the renderer.
+    pub allocator: std::mem::ManuallyDrop<SharedAllocator>,
+    /// Drawn in order every frame.
+    pub meshes: Vec<VertexBuffer>,
+    /// Application callbacks recorded into every frame.
+    pub hooks: RenderHooks,
     pub pools: CommandPools,
-    pub commandbuffers: Vec<vk::CommandBuffer>,
+    /// Uploads data into device-local buffers on the transfer queue.
+    pub staging: std::mem::ManuallyDrop<StagingBuffer>,
+    pub graphics_queue_runner: QueueRunner,
+    pub surface_size: SurfaceSize,
+    pub clear_color: [f32; 4],
+    // set when the swapchain no longer fits the surface
+    swapchain_outdated: bool,
+    disabled_features: Vec<DisabledFeature>,
+    debug_callback: Option<DebugCallback>,
+    // time between two vblanks of the display the window is on, if known
+    frame_interval: Option<Duration>,
+    last_present: Option<Instant>,
 }
 
 impl VulkanRenderer {
-    fn used_layer_names() -> Vec<std::ffi::CString> {
-        vec![
-            std::ffi::CString::new("VK_LAYER_KHRONOS_validation").unwrap()
-        ]
+    fn used_layer_names(builder: &RendererBuilder) -> Vec<std::ffi::CString> {
+        let mut layer_names = vec![];
+        if builder.debug_settings.validation {
+            layer_names.push(debug::VALIDATION_LAYER.to_owned());
+        }
+        layer_names.extend(builder.layers.iter().cloned());
+        layer_names
     }
 
-    fn used_extensions() -> Vec<*const i8> {
-        vec![
-            ash::extensions::ext::DebugUtils::name().as_ptr(),
-            ash::extensions::khr::Surface::name().as_ptr(),
-            ash::extensions::khr::XlibSurface::name().as_ptr(),
-        ]
+    fn used_extensions(builder: &RendererBuilder) -> Vec<*const i8> {
+        let mut extensions = Surface::required_extensions();
+        // MoltenVK is only enumerated when portability drivers are asked for
+        #[cfg(target_os = "macos")]
+        extensions.push(vk::KhrPortabilityEnumerationFn::name().as_ptr());
+        if builder.debug_settings.validation {
+            extensions.push(ash::extensions::ext::DebugUtils::name().as_ptr());
+            if builder.debug_settings.shader_printf {
+                extensions.push(vk::ExtValidationFeaturesFn::name().as_ptr());
+            }
+        }
+        extensions.extend(builder.instance_extensions.iter().map(|name| name.as_ptr()));
+        extensions
     }
 
-    pub fn new(
+    /// Fails with the first layer or extension the Vulkan installation does
+    /// not provide; extensions may also come from the enabled layers.
+    fn check_instance_support(
+        entry: &ash::Entry,
+        layer_names: &[std::ffi::CString],
+        extension_name_pointers: &[*const i8],
+    ) -> Result<(), RendererError> {
+        let available_layers =
+            entry.enumerate_instance_layer_properties().map_err(RendererError::Instance)?;
+        if let Some(missing) = layer_names
+            .iter()
+            .find(|name| !debug::has_layer(&available_layers, name))
+        {
+            return Err(RendererError::MissingLayer(missing.clone()));
+        }
+        let mut available_extensions = entry
+            .enumerate_instance_extension_properties(None)
+            .map_err(RendererError::Instance)?;
+        for layer_name in layer_names {
+            available_extensions.extend(
+                entry
+                    .enumerate_instance_extension_properties(Some(layer_name))
+                    .map_err(RendererError::Instance)?,
+            );
+        }
+        for &extension_name in extension_name_pointers {
+            let extension_name = unsafe { std::ffi::CStr::from_ptr(extension_name) };
+            if !device::has_extension(&available_extensions, extension_name) {
+                return Err(RendererError::MissingExtension(extension_name.to_owned()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a renderer with the default settings; see [`RendererBuilder`]
+    /// to change them.
+    pub fn new(window: winit::window::Window) -> Result<VulkanRenderer, RendererError> {
+        RendererBuilder::new().build(window)
+    }
+
+    /// Sets up the instance, device, swapchain and pipeline for `window`;
+    /// command buffers are recorded per frame in `render_frame`. Whatever was
+    /// created before a step fails is destroyed again, newest first.
+    pub(crate) fn init(
         window: winit::window::Window,
-    ) -> Result<VulkanRenderer, Box<dyn std::error::Error>> {
+        builder: RendererBuilder,
+    ) -> Result<VulkanRenderer, RendererError> {
         let entry = ash::Entry::linked();
-        let used_layer_names = Self::used_layer_names();
-        let used_layers = used_layer_names.iter()
+        let mut builder = builder;
+        let mut disabled_features = builder
+            .debug_settings
+            .disable_unavailable(&entry)
+            .map_err(RendererError::Instance)?;
+        let used_layer_names = Self::used_layer_names(&builder);
+        let used_layers: Vec<*const i8> = used_layer_names.iter()
             .map(|layer_name| layer_name.as_ptr())
             .collect();
-        let used_extensions = Self::used_extensions();
-        let instance = Self::create_instance(&entry, &used_layers, &used_extensions)?;
-        let debug = Debug::new(&entry, &instance)?;
+        let used_extensions = Self::used_extensions(&builder);
+        Self::check_instance_support(&entry, &used_layer_names, &used_extensions)?;
+        let instance = Self::create_instance(
+            &entry,
+            &builder,
+            &used_layers,
+            &used_extensions,
+        )?;
+        let instance = CleanupGuard::new(instance, |instance: &mut ash::Instance| unsafe {
+            instance.destroy_instance(None)
+        });
+        let debug = if builder.debug_settings.validation {
+            Some(
+                Debug::new(&entry, &instance, builder.debug_callback.clone())
+                    .map_err(RendererError::Instance)?,
+            )
+        } else {
+            None
+        };
         let surfaces = Surface::new(&window, &entry, &instance)?;
-        let device = Device::new(&instance, &used_layers)?;
-        let mut swapchain = Swapchain::new(
+        let surface_size = SurfaceSize::of_window(&window);
+        let frame_interval = display::frame_interval(&window);
+        let device_selector = DeviceSelector::from_env().or(builder.device_selector.clone());
+        let device_info = Device::select(
+            &instance,
+            &surfaces,
+            device_selector.as_ref(),
+            builder.debug_callback.as_ref(),
+        )?;
+        let device = Device::new(
+            &instance,
+            &surfaces,
+            device_info,
+            &used_layers,
+            &builder.debug_settings,
+            &builder.device_features,
+            &builder.device_extensions,
+        )?;
+        disabled_features.extend(device.disabled_features.iter().cloned());
+        let device = CleanupGuard::new(device, |device: &mut Device| unsafe { device.cleanup() });
+        let logical_device = device.logical_device.clone();
+        let swapchain = Swapchain::new(
             &instance, 
             &surfaces, 
             &device,
+            &builder.swapchain_settings,
+            Self::window_extent(&surface_size),
         )?;
+        let mut swapchain = CleanupGuard::new(swapchain, |swapchain: &mut Swapchain| unsafe {
+            swapchain.cleanup(&logical_device)
+        });
         let renderpass = Self::create_renderpass(
             &device.logical_device, 
             swapchain.surface_format.format
-        )?;
-        swapchain.create_framebuffer(&device.logical_device, renderpass)?;
-        let pipeline = Pipeline::new(
-            &instance,
-            &device.physical_device,
+        ).map_err(RendererError::Swapchain)?;
+        let renderpass = CleanupGuard::new(renderpass, |renderpass: &mut vk::RenderPass| unsafe {
+            logical_device.destroy_render_pass(*renderpass, None)
+        });
+        swapchain
+            .create_framebuffer(&device.logical_device, *renderpass)
+            .map_err(RendererError::Swapchain)?;
+        let allocator: SharedAllocator = Arc::new(Mutex::new(Allocator::new(&AllocatorCreateDesc {
+            instance: instance.clone(),
+            device: device.logical_device.clone(),
+            physical_device: device.physical_device,
+            debug_settings: Default::default(),
+            buffer_device_address: false,
+        })?));
+        let pipeline = Pipeline::new::<ColoredVertex>(
             &device.logical_device, 
             &swapchain, 
             &renderpass,
+            &builder.pipeline_settings,
+            &device.enabled_features,
         )?;
-        let command_pools = CommandPools::new(&device.logical_device, &device.queue_families)?;
-        let commandbuffers =
-            CommandPools::create_commandbuffers(&device.logical_device, &command_pools, swapchain.framebuffers.len())?;
-        Self::fill_commandbuffers(
-            &commandbuffers,
+        let pipeline = CleanupGuard::new(pipeline, |pipeline: &mut Pipeline| {
+            pipeline.cleanup(&logical_device)
+        });
+        let command_pools = CommandPools::new(&device.queue_families)?;
+        let command_pools = CleanupGuard::new(command_pools, |pools: &mut CommandPools| {
+            pools.cleanup(&logical_device)
+        });
+        let mut staging = StagingBuffer::new(
             &device.logical_device,
-            &renderpass,
-            &swapchain, 
-            &pipeline,
+            &allocator,
+            staging::DEFAULT_CAPACITY,
         )?;
+        let triangle =
+            VertexBuffer::new_device_local(&device, &allocator, &mut staging, &vertex::TRIANGLE)?;
+        let graphics_queue_runner = QueueRunner::new(device.queues.graphics_queue);
+        // nothing can fail from here on, so the renderer takes over the cleanup
         Ok(VulkanRenderer { 
-            window,
+            window: WindowController::new(window),
             entry, 
-            instance, 
-            debug: std::mem::ManuallyDrop::new(debug), 
+            instance: CleanupGuard::into_inner(instance), 
+            debug, 
             surfaces: std::mem::ManuallyDrop::new(surfaces), 
-            device,
-            swapchain,
-            renderpass,
-            pipeline,
-            pools: command_pools,
-            commandbuffers,
+            device: CleanupGuard::into_inner(device),
+            swapchain: CleanupGuard::into_inner(swapchain),
+            renderpass: CleanupGuard::into_inner(renderpass),
+            pipeline: CleanupGuard::into_inner(pipeline),
+            allocator: std::mem::ManuallyDrop::new(allocator),
+            meshes: vec![triangle],
+            hooks: RenderHooks::default(),
+            pools: CleanupGuard::into_inner(command_pools),
+            staging: std::mem::ManuallyDrop::new(staging),
+            graphics_queue_runner,
+            surface_size,
+            clear_color: builder.clear_color,
+            swapchain_outdated: false,
+            disabled_features,
+            debug_callback: builder.debug_callback,
+            frame_interval,
+            last_present: None,
         })
     }
 
+    /// Records the new DPI scale when the window moves to another monitor or
+    /// the system scale changes; `new_inner_size` is winit's suggested size.
+    pub fn scale_factor_changed(
+        &mut self,
+        scale_factor: f64,
+        new_inner_size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        self.surface_size = SurfaceSize::new(new_inner_size, scale_factor);
+        // before the swapchain is recreated for the new size
+        self.surfaces.set_scale_factor(self.window.window(), scale_factor);
+        self.swapchain_outdated = true;
+        // usually the window moved to another monitor
+        self.display_changed();
+    }
+
+    /// Re-reads the refresh rate frames are paced to, e.g. after the window
+    /// moved to another monitor.
+    pub fn display_changed(&mut self) {
+        self.frame_interval = display::frame_interval(self.window.window());
+    }
+
+    /// Switches to exclusive fullscreen with `mode`, or back to windowed
+    /// mode, and paces frames to the new refresh rate.
+    pub fn set_display_mode(&mut self, mode: Option<winit::monitor::VideoMode>) {
+        display::set_display_mode(self.window.window(), mode);
+        self.display_changed();
+    }
+
+    /// When the next frame should start so frames are not rendered faster
+    /// than the display refreshes. `None` if there is no need to wait: the
+    /// present mode already waits for vertical blank, or the refresh rate is
+    /// unknown.
+    pub fn next_frame_at(&self) -> Option<Instant> {
+        let waits_for_vblank = matches!(
+            self.swapchain.present_mode,
+            vk::PresentModeKHR::FIFO | vk::PresentModeKHR::FIFO_RELAXED
+        );
+        if waits_for_vblank {
+            return None;
+        }
+        Some(self.last_present? + self.frame_interval?)
+    }
+
+    /// Renders and presents one frame. Returns `OutOfDate` without drawing
+    /// if the swapchain no longer matched the surface, and `Suboptimal` if it
+    /// still worked but did not fit. Either way the swapchain is recreated,
+    /// right away or before the next frame. While the window is minimized
+    /// nothing is drawn and `Minimized` is returned.
+    pub fn render_frame(&mut self) -> Result<SwapchainStatus, RendererError> {
+        if self.is_minimized() {
+            return Ok(SwapchainStatus::Minimized);
+        }
+        if self.swapchain_outdated
+            && self.recreate_swapchain()? == SwapchainStatus::Minimized
+        {
+            return Ok(SwapchainStatus::Minimized);
+        }
+        let acquired = self
+            .swapchain
+            .acquire(&self.device.logical_device)
+            .map_err(RendererError::Swapchain)?;
+        let frame = match acquired {
+            Acquired::Frame(frame) => frame,
+            Acquired::OutOfDate => {
+                self.swapchain_outdated = true;
+                self.recreate_swapchain()?;
+                return Ok(SwapchainStatus::OutOfDate);
+            }
+        };
+        // the frame's fence was waited on, so its command buffers are free again
+        self.pools
+            .reset_frame(&self.device.logical_device, frame.frame_index)
+            .map_err(RendererError::CommandBuffer)?;
+        let guarded = self.pools
+            .frame_commandbuffer(&self.device.logical_device, frame.frame_index)
+            .map_err(RendererError::CommandBuffer)?;
+        let commandbuffer = self.pools.commandbuffer(&guarded)?;
+        let context = HookContext {
+            logical_device: &self.device.logical_device,
+            commandbuffer,
+            framebuffer: frame.framebuffer(),
+            extent: frame.extent(),
+            image_index: frame.image_index,
+            frame_index: frame.frame_index,
+        };
+        Self::record_commandbuffer(
+            &context,
+            &self.renderpass,
+            &self.pipeline,
+            &self.meshes,
+            &mut self.hooks,
+            self.clear_color,
+        ).map_err(RendererError::CommandBuffer)?;
+        self.graphics_queue_runner.push(Submission {
+            commandbuffers: vec![commandbuffer],
+            wait_semaphores: vec![frame.image_available()],
+            wait_stages: vec![vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
+            signal_semaphores: vec![frame.rendering_finished()],
+        });
+        frame
+            .reset_fence(&self.device.logical_device)
+            .map_err(RendererError::Submit)?;
+        if let Err(err) = self.graphics_queue_runner
+            .flush(&self.device.logical_device, frame.may_begin_drawing())
+        {
+            // nothing else will signal the reset fence, and the next acquire
+            // of this slot would wait for it forever; if this fails too the
+            // device is lost and the wait returns that instead
+            let _ = frame
+                .signal_fence(&self.device.logical_device, self.device.queues.graphics_queue);
+            return Err(RendererError::Submit(err));
+        }
+        let acquire_status = frame.status;
+        let present_status = frame
+            .present(self.device.queues.present_queue)
+            .map_err(RendererError::Present)?;
+        self.swapchain.advance_frame();
+        self.window.frame_presented();
+        self.last_present = Some(Instant::now());
+        let status = if acquire_status == SwapchainStatus::Suboptimal
+            && present_status == SwapchainStatus::Optimal
+        {
+            SwapchainStatus::Suboptimal
+        } else {
+            present_status
+        };
+        if status != SwapchainStatus::Optimal {
+            self.swapchain_outdated = true;
+        }
+        Ok(status)
+    }
+
+    /// Rebuilds the swapchain and its framebuffers for the surface's current
+    /// size. `render_frame` does this on its own after
+    /// a resize or when presenting reports the swapchain out of date.
+    /// Returns `Minimized` and tries again on the next call if the surface
+    /// has no area right now.
+    pub fn recreate_swapchain(&mut self) -> Result<SwapchainStatus, RendererError> {
+        unsafe { self.device.logical_device.device_wait_idle() }
+            .map_err(RendererError::Device)?;
+        let status = self.swapchain
+            .recreate(&self.surfaces, &self.device, Self::window_extent(&self.surface_size))
+            .map_err(RendererError::Swapchain)?;
+        if status == SwapchainStatus::Minimized {
+            self.swapchain_outdated = true;
+            return Ok(status);
+        }
+        self.swapchain
+            .create_framebuffer(&self.device.logical_device, self.renderpass)
+            .map_err(RendererError::Swapchain)?;
+        self.pipeline.resize(self.swapchain.extent);
+        self.swapchain_outdated = false;
+        Ok(SwapchainStatus::Optimal)
+    }
+
+    /// Uploads `vertices` as a new mesh that is drawn from the next frame on,
+    /// after the ones added before; returns its index in `meshes`.
+    pub fn add_mesh(&mut self, vertices: &[ColoredVertex]) -> Result<usize, RendererError> {
+        let mesh = VertexBuffer::new_device_local(
+            &self.device,
+            &self.allocator,
+            &mut self.staging,
+            vertices,
+        )?;
+        self.meshes.push(mesh);
+        Ok(self.meshes.len() - 1)
+    }
+
+    /// Records `hook` into every frame at `point`, after the hooks added
+    /// before. Hooks inside the main pass inherit its pipeline and dynamic
+    /// state and must bind their own if they differ.
+    pub fn add_render_hook(&mut self, point: InjectionPoint, hook: RenderHook) {
+        self.hooks.add(point, hook);
+    }
+
+    /// Every physical device with its score for this renderer's surface, e.g.
+    /// for a GPU picker whose choice is passed to `RendererBuilder::device`
+    /// on the next start.
+    pub fn available_devices(&self) -> Result<Vec<PhysicalDeviceInfo>, RendererError> {
+        Device::enumerate(&self.instance, &self.surfaces, self.debug_callback.as_ref())
+            .map_err(RendererError::Device)
+    }
+
+    /// Debug features that were requested but turned off because the layer
+    /// or extension they need is missing.
+    pub fn disabled_features(&self) -> &[DisabledFeature] {
+        &self.disabled_features
+    }
+
+    /// Whether the window currently has no area to draw into.
+    pub fn is_minimized(&self) -> bool {
+        swapchain::is_zero_extent(Self::window_extent(&self.surface_size))
+    }
+
+    /// Switches the channel debug view, starting with the next frame.
+    pub fn set_channel_view(&mut self, channel_view: ChannelView) {
+        self.pipeline.channel_view = channel_view;
+    }
+
+    /// Switches the present mode; the swapchain is recreated before the next
+    /// frame. Falls back to FIFO if the surface does not support `present_mode`.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        self.swapchain.settings.present_mode = present_mode;
+        self.swapchain_outdated = true;
+    }
+
+    /// Turns vsync on (FIFO) or off (immediate presentation).
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.set_present_mode(if vsync { PresentMode::Fifo } else { PresentMode::Immediate });
+    }
+
+    /// Records the new window size; the swapchain is recreated before the
+    /// next frame.
+    pub fn resized(&mut self, physical_size: winit::dpi::PhysicalSize<u32>) {
+        self.surface_size = SurfaceSize::new(physical_size, self.surface_size.scale_factor);
+        self.swapchain_outdated = true;
+    }
+
+    fn window_extent(surface_size: &SurfaceSize) -> vk::Extent2D {
+        vk::Extent2D {
+            width: surface_size.physical.width,
+            height: surface_size.physical.height,
+        }
+    }
+
     fn create_instance(
         entry: &ash::Entry,
-        layer_name_pointers: &Vec<*const i8>,
-        extension_name_pointers: &Vec<*const i8>,
-    ) -> Result<ash::Instance, vk::Result> {
-        let enginename = std::ffi::CString::new("UnknownGameEngine").unwrap();
-        let appname = std::ffi::CString::new("The Black Window").unwrap();
+        builder: &RendererBuilder,
+        layer_name_pointers: &[*const i8],
+        extension_name_pointers: &[*const i8],
+    ) -> Result<ash::Instance, RendererError> {
+        let enginename = std::ffi::CString::new("UnknownGameEngine")?;
+        let appname = std::ffi::CString::new(builder.app_name.as_str())?;
         let app_info = vk::ApplicationInfo::builder()
             .engine_name(&enginename)
             .application_name(&appname)
-            .application_version(vk::make_api_version(0, 0, 1, 0))
+            .application_version(builder.app_version)
             .engine_version(vk::make_api_version(0, 0, 1, 0))
-            .api_version(vk::API_VERSION_1_1);
-        let instance_create_info = vk::InstanceCreateInfo::builder() 
+            .api_version(builder.api_version);
+        let enabled_validation_features = [vk::ValidationFeatureEnableEXT::DEBUG_PRINTF];
+        let mut validation_features = vk::ValidationFeaturesEXT::builder()
+            .enabled_validation_features(&enabled_validation_features);
+        let mut instance_create_info = vk::InstanceCreateInfo::builder() 
             .application_info(&app_info)
-            .enabled_layer_names(&layer_name_pointers)
-            .enabled_extension_names(&extension_name_pointers);
+            .enabled_layer_names(layer_name_pointers)
+            .enabled_extension_names(extension_name_pointers);
+        if builder.debug_settings.validation && builder.debug_settings.shader_printf {
+            instance_create_info = instance_create_info.push_next(&mut validation_features);
+        }
+        if cfg!(target_os = "macos") {
+            instance_create_info =
+                instance_create_info.flags(vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR);
+        }
         unsafe { entry.create_instance(&instance_create_info, None) }
+            .map_err(RendererError::Instance)
     }
 
     fn create_renderpass(
@@ -157,46 +570,58 @@ impl VulkanRenderer {
     }
 
 
-    fn fill_commandbuffers(
-        commandbuffers: &[vk::CommandBuffer],
-        logical_device: &ash::Device,
+    fn record_commandbuffer(
+        context: &HookContext,
         renderpass: &vk::RenderPass,
-        swapchain: &Swapchain,
         pipeline: &Pipeline,
+        meshes: &[VertexBuffer],
+        hooks: &mut RenderHooks,
+        clear_color: [f32; 4],
     ) -> Result<(), vk::Result> {
-        for (i, &commandbuffer) in commandbuffers.iter().enumerate() {
-            let commmandbuffer_begininfo = vk::CommandBufferBeginInfo::builder();
-            unsafe {
-                logical_device.begin_command_buffer(commandbuffer, &commmandbuffer_begininfo)?;
+        let logical_device = context.logical_device;
+        let commandbuffer = context.commandbuffer;
+        let commmandbuffer_begininfo = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe {
+            logical_device.begin_command_buffer(commandbuffer, &commmandbuffer_begininfo)?;
+        }
+        hooks.run(InjectionPoint::BeforeMainPass, context);
+        let clearvalues = [vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: clear_color,
+            },
+        }];
+        let renderpass_begininfo = vk::RenderPassBeginInfo::builder()
+            .render_pass(*renderpass)
+            .framebuffer(context.framebuffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: context.extent,
+            })
+            .clear_values(&clearvalues);
+        unsafe {
+            logical_device.cmd_begin_render_pass(
+                commandbuffer, 
+                &renderpass_begininfo, 
+                vk::SubpassContents::INLINE,
+            );
+            logical_device.cmd_bind_pipeline(
+                commandbuffer, 
+                vk::PipelineBindPoint::GRAPHICS, 
+                pipeline.pipeline
+            );
+            pipeline.set_dynamic_state(logical_device, commandbuffer);
+            for mesh in meshes {
+                mesh.draw(logical_device, commandbuffer);
             }
-            let clearvalues = [vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [0.0, 0.0, 0.08, 1.0],
-                },
-            }];
-            let renderpass_begininfo = vk::RenderPassBeginInfo::builder()
-                .render_pass(*renderpass)
-                .framebuffer(swapchain.framebuffers[i])
-                .render_area(vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent: swapchain.extent,
-                })
-                .clear_values(&clearvalues);
-                unsafe {
-                    logical_device.cmd_begin_render_pass(
-                        commandbuffer, 
-                        &renderpass_begininfo, 
-                        vk::SubpassContents::INLINE,
-                    );
-                    logical_device.cmd_bind_pipeline(
-                        commandbuffer, 
-                        vk::PipelineBindPoint::GRAPHICS, 
-                        pipeline.pipeline
-                    );
-                    logical_device.cmd_draw(commandbuffer, 1, 1, 0, 0);
-                    logical_device.cmd_end_render_pass(commandbuffer);
-                    logical_device.end_command_buffer(commandbuffer)?;
-                }
+        }
+        hooks.run(InjectionPoint::MainPass, context);
+        unsafe {
+            logical_device.cmd_end_render_pass(commandbuffer);
+        }
+        hooks.run(InjectionPoint::AfterMainPass, context);
+        unsafe {
+            logical_device.end_command_buffer(commandbuffer)?;
         }
         Ok(())
     }
@@ -205,18 +630,20 @@ impl VulkanRenderer {
 impl Drop for VulkanRenderer {
     fn drop(&mut self) {
          unsafe { 
-             self.device
-                 .logical_device
-                 .device_wait_idle()
-                 .expect("something wrong while wating");
+             // nothing sensible can be done about a lost device while tearing down
+             let _ = self.device.logical_device.device_wait_idle();
              self.pools.cleanup(&self.device.logical_device);
+             // buffers free their memory when dropped, which has to happen
+             // before the allocator and the device go away
+             self.meshes.clear();
+             std::mem::ManuallyDrop::drop(&mut self.staging);
              self.pipeline.cleanup(&self.device.logical_device);
              self.device.logical_device.destroy_render_pass(self.renderpass, None);
              self.swapchain.cleanup(&self.device.logical_device);
-             self.device.logical_device.destroy_device(None);
-             std::mem::ManuallyDrop::drop(&mut self.surfaces);
+             std::mem::ManuallyDrop::drop(&mut self.allocator);
              self.device.cleanup();
-             std::mem::ManuallyDrop::drop(&mut self.debug);
+             std::mem::ManuallyDrop::drop(&mut self.surfaces);
+             drop(self.debug.take());
              self.instance.destroy_instance(None)
          };       
     }
@@ -1,129 +1,340 @@
 use ash::vk;
-use gpu_allocator::{vulkan::{Allocator, AllocatorCreateDesc, AllocationCreateDesc}, MemoryLocation};
+use crate::renderer::cleanup_guard::CleanupGuard;
+use crate::renderer::device::DeviceFeatures;
+use crate::renderer::error::RendererError;
+use crate::renderer::projection::LogicalResolution;
 use crate::renderer::swapchain::Swapchain;
+use crate::renderer::vertex::Vertex;
+
+/// Offsets written depth values, e.g. against shadow acne or decal
+/// z-fighting. A non-zero `clamp` needs `DeviceFeatures::depth_bias_clamp`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub slope_factor: f32,
+    pub clamp: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Opaque,
+    /// Straight (non-premultiplied) alpha blending.
+    Alpha,
+    Additive,
+    Premultiplied,
+    Multiply,
+}
+
+impl BlendMode {
+    pub fn attachment_state(
+        self,
+        write_mask: vk::ColorComponentFlags,
+    ) -> vk::PipelineColorBlendAttachmentState {
+        let (src_color, dst_color, src_alpha, dst_alpha) = match self {
+            BlendMode::Opaque => (
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ZERO,
+            ),
+            BlendMode::Alpha => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendMode::Additive => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE,
+            ),
+            BlendMode::Premultiplied => (
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendMode::Multiply => (
+                vk::BlendFactor::DST_COLOR,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ONE,
+            ),
+        };
+        vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(self != BlendMode::Opaque)
+            .src_color_blend_factor(src_color)
+            .dst_color_blend_factor(dst_color)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(src_alpha)
+            .dst_alpha_blend_factor(dst_alpha)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(write_mask)
+            .build()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColorAttachment {
+    pub blend: BlendMode,
+    /// Channels the pipeline writes; the others keep their previous value.
+    pub write_mask: vk::ColorComponentFlags,
+}
+
+impl From<BlendMode> for ColorAttachment {
+    fn from(blend: BlendMode) -> Self {
+        ColorAttachment {
+            blend,
+            write_mask: vk::ColorComponentFlags::RGBA,
+        }
+    }
+}
+
+/// Debug view that shows a single channel of the rendered color as
+/// grayscale, for checking blending and channel packing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChannelView {
+    #[default]
+    All,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+impl ChannelView {
+    pub fn next(self) -> ChannelView {
+        match self {
+            ChannelView::All => ChannelView::Red,
+            ChannelView::Red => ChannelView::Green,
+            ChannelView::Green => ChannelView::Blue,
+            ChannelView::Blue => ChannelView::Alpha,
+            ChannelView::Alpha => ChannelView::All,
+        }
+    }
+
+    // matches the `channel` push constant in shader.frag
+    fn shader_value(self) -> u32 {
+        match self {
+            ChannelView::All => 0,
+            ChannelView::Red => 1,
+            ChannelView::Green => 2,
+            ChannelView::Blue => 3,
+            ChannelView::Alpha => 4,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PipelineSettings {
+    pub topology: vk::PrimitiveTopology,
+    /// Lets the special index returned by `restart_index` cut strips and
+    /// fans; list topologies do not support it.
+    pub primitive_restart: bool,
+    /// Faces to discard; `NONE` draws double-sided geometry.
+    pub cull_mode: vk::CullModeFlags,
+    /// Winding order of front faces.
+    pub front_face: vk::FrontFace,
+    pub depth_bias: Option<DepthBias>,
+    /// Makes the depth bias dynamic state, so it can be changed per draw with
+    /// `Pipeline::set_depth_bias`; `depth_bias` is then the initial value.
+    pub dynamic_depth_bias: bool,
+    /// Draws into the part of the framebuffer given by the logical
+    /// resolution's presentation policy instead of all of it.
+    pub logical_resolution: Option<LogicalResolution>,
+    /// Initial scissor of every command buffer; `None` covers the whole
+    /// viewport. It can be narrowed per draw with `Pipeline::set_scissor`.
+    pub scissor: Option<vk::Rect2D>,
+    /// Blending and write mask of each color attachment of the subpass, in
+    /// order. The renderer's render pass has exactly one.
+    pub color_attachments: Vec<ColorAttachment>,
+}
+
+impl Default for PipelineSettings {
+    fn default() -> Self {
+        PipelineSettings {
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart: false,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            depth_bias: None,
+            dynamic_depth_bias: false,
+            logical_resolution: None,
+            scissor: None,
+            color_attachments: vec![BlendMode::Alpha.into()],
+        }
+    }
+}
+
+/// Index value that restarts a strip or fan when primitive restart is on.
+pub fn restart_index(index_type: vk::IndexType) -> u32 {
+    match index_type {
+        vk::IndexType::UINT16 => u16::MAX as u32,
+        vk::IndexType::UINT8_EXT => u8::MAX as u32,
+        _ => u32::MAX,
+    }
+}
+
+fn viewport_and_scissor(settings: &PipelineSettings, extent: vk::Extent2D) -> (vk::Viewport, vk::Rect2D) {
+    let viewport_area = match settings.logical_resolution {
+        Some(logical_resolution) => logical_resolution.viewport(extent),
+        None => vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        },
+    };
+    let viewport = vk::Viewport {
+        x: viewport_area.offset.x as f32,
+        y: viewport_area.offset.y as f32,
+        width: viewport_area.extent.width as f32,
+        height: viewport_area.extent.height as f32,
+        min_depth: 0.,
+        max_depth: 1.,
+    };
+    (viewport, settings.scissor.unwrap_or(viewport_area))
+}
+
+fn supports_primitive_restart(topology: vk::PrimitiveTopology) -> bool {
+    matches!(
+        topology,
+        vk::PrimitiveTopology::LINE_STRIP
+            | vk::PrimitiveTopology::TRIANGLE_STRIP
+            | vk::PrimitiveTopology::TRIANGLE_FAN
+            | vk::PrimitiveTopology::LINE_STRIP_WITH_ADJACENCY
+            | vk::PrimitiveTopology::TRIANGLE_STRIP_WITH_ADJACENCY
+    )
+}
 
 pub struct Pipeline {
     pub pipeline: vk::Pipeline,
+    pub settings: PipelineSettings,
+    pub channel_view: ChannelView,
+    viewport: vk::Viewport,
+    scissor: vk::Rect2D,
     layout: vk::PipelineLayout,
-    allocator: Allocator,
 }
 impl Pipeline {
-    pub fn new(
-        instance: &ash::Instance,
-        physical_device: &vk::PhysicalDevice,
+    /// Builds the pipeline with the vertex input layout of `V`, which has to
+    /// provide the attributes the built-in shaders read. `enabled_features`
+    /// are the ones the device was created with.
+    pub fn new<V: Vertex>(
         logical_device: &ash::Device,
         swapchain: &Swapchain,
         renderpass: &vk::RenderPass,
-    ) -> Result<Pipeline, vk::Result> {
+        settings: &PipelineSettings,
+        enabled_features: &DeviceFeatures,
+    ) -> Result<Pipeline, RendererError> {
+        if settings.primitive_restart && !supports_primitive_restart(settings.topology) {
+            return Err(RendererError::InvalidPipelineSettings(
+                "primitive restart needs a strip or fan topology",
+            ));
+        }
+        if settings.color_attachments.len() != 1 {
+            return Err(RendererError::InvalidPipelineSettings(
+                "the render pass has exactly one color attachment",
+            ));
+        }
+        let clamped = settings.depth_bias.is_some_and(|depth_bias| depth_bias.clamp != 0.0);
+        if clamped && !enabled_features.depth_bias_clamp {
+            return Err(RendererError::InvalidPipelineSettings(
+                "a depth bias clamp needs the depth_bias_clamp device feature",
+            ));
+        }
         let vertexshader_createinfo = vk::ShaderModuleCreateInfo::builder()
             .code(
                 vk_shader_macros::include_glsl!("./shaders/shader.vert", kind: vert),
             );
+        // the modules are only needed until the pipeline is created, and are
+        // destroyed when their guards go out of scope on every path
+        let destroy_module = |module: &mut vk::ShaderModule| unsafe {
+            logical_device.destroy_shader_module(*module, None)
+        };
         let vertexshader_module =
-            unsafe { logical_device.create_shader_module(&vertexshader_createinfo, None)? };
+            unsafe { logical_device.create_shader_module(&vertexshader_createinfo, None) }
+                .map_err(RendererError::Pipeline)?;
+        let vertexshader_module = CleanupGuard::new(vertexshader_module, destroy_module);
         let fragmentshader_createinfo = vk::ShaderModuleCreateInfo::builder()
             .code(vk_shader_macros::include_glsl!("./shaders/shader.frag"));
         let fragmentshader_module =
-            unsafe { logical_device.create_shader_module(&fragmentshader_createinfo, None)? };
-        let mainfunctionname = std::ffi::CString::new("main").unwrap();
+            unsafe { logical_device.create_shader_module(&fragmentshader_createinfo, None) }
+                .map_err(RendererError::Pipeline)?;
+        let fragmentshader_module = CleanupGuard::new(fragmentshader_module, destroy_module);
+        let mainfunctionname = std::ffi::CString::new("main")?;
         let vertexshader_stage = vk::PipelineShaderStageCreateInfo::builder()
             .stage(vk::ShaderStageFlags::VERTEX)
-            .module(vertexshader_module)
+            .module(*vertexshader_module)
             .name(&mainfunctionname);
         let fragmentshader_stage = vk::PipelineShaderStageCreateInfo::builder()
             .stage(vk::ShaderStageFlags::FRAGMENT)
-            .module(fragmentshader_module)
+            .module(*fragmentshader_module)
             .name(&mainfunctionname);
         let shader_stages = vec![vertexshader_stage.build(), fragmentshader_stage.build()];
-        let vertex_attribute_descriptions = [vk::VertexInputAttributeDescription {
-            binding: 0,
-            location: 0,
-            offset: 0,
-            format: vk::Format::R32G32B32A32_SFLOAT,
-        }];
-        let vertex_binding_descriptions = [vk::VertexInputBindingDescription {
-            binding: 0,
-            stride: 16,
-            input_rate: vk::VertexInputRate::VERTEX,
-        }];
+        let vertex_bindings = [V::binding_description()];
+        let vertex_attributes = V::attribute_descriptions();
         let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
-            .vertex_attribute_descriptions(&vertex_attribute_descriptions)
-            .vertex_binding_descriptions(&vertex_binding_descriptions);
-        let mut allocator = Allocator::new(&AllocatorCreateDesc {
-            instance: instance.clone(),
-            device: logical_device.clone(),
-            physical_device: physical_device.clone(),
-            debug_settings: Default::default(),
-            buffer_device_address: false,
-        }).unwrap();
-        let vk_info = vk::BufferCreateInfo::builder()
-            .size(16)
-            .usage(vk::BufferUsageFlags::VERTEX_BUFFER);
-        let buffer = unsafe { logical_device.create_buffer(&vk_info, None) }?;
-        let requirements = unsafe { logical_device.get_buffer_memory_requirements(buffer) };
-        let allocation = allocator
-            .allocate(&AllocationCreateDesc { name: "Example allocation",
-                requirements,
-                location: MemoryLocation::CpuToGpu,
-                linear: true, // Buffers are always linear
-            }).unwrap();
-        unsafe { 
-            logical_device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset()).unwrap() 
-        };
-        // allocator.
+            .vertex_binding_descriptions(&vertex_bindings)
+            .vertex_attribute_descriptions(&vertex_attributes);
         let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
-            .topology(vk::PrimitiveTopology::POINT_LIST);
-        let viewports = [vk::Viewport {
-            x: 0.,
-            y: 0.,
-            width: swapchain.extent.width as f32,
-            height: swapchain.extent.height as f32,
-            min_depth: 0.,
-            max_depth: 1.,
-        }];
-        let scissors = [vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: swapchain.extent,
-        }];
+            .topology(settings.topology)
+            .primitive_restart_enable(settings.primitive_restart);
+        // both are dynamic state, so the pipeline survives a resized swapchain
+        let (viewport, scissor) = viewport_and_scissor(settings, swapchain.extent);
         let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
-            .viewports(&viewports)
-            .scissors(&scissors);
+            .viewport_count(1)
+            .scissor_count(1);
         let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
             .line_width(1.0)
-            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-            .cull_mode(vk::CullModeFlags::NONE)
-            .polygon_mode(vk::PolygonMode::FILL);
+            .front_face(settings.front_face)
+            .cull_mode(settings.cull_mode)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .depth_bias_enable(settings.depth_bias.is_some() || settings.dynamic_depth_bias);
+        let rasterizer_info = match settings.depth_bias {
+            Some(depth_bias) if !settings.dynamic_depth_bias => rasterizer_info
+                .depth_bias_constant_factor(depth_bias.constant_factor)
+                .depth_bias_slope_factor(depth_bias.slope_factor)
+                .depth_bias_clamp(depth_bias.clamp),
+            _ => rasterizer_info,
+        };
         let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
             .rasterization_samples(vk::SampleCountFlags::TYPE_1);
-        let colorblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
-            .blend_enable(true)
-            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-            .color_blend_op(vk::BlendOp::ADD)
-            .src_alpha_blend_factor(vk::BlendFactor::SRC_ALPHA)
-            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-            .alpha_blend_op(vk::BlendOp::ADD)
-            .color_write_mask(
-                vk::ColorComponentFlags::R
-                | vk::ColorComponentFlags::G
-                | vk::ColorComponentFlags::B
-                | vk::ColorComponentFlags::A,
-            )
-            .build()];
+        let colorblend_attachments: Vec<vk::PipelineColorBlendAttachmentState> = settings
+            .color_attachments
+            .iter()
+            .map(|attachment| attachment.blend.attachment_state(attachment.write_mask))
+            .collect();
         let colorblend_info = vk::PipelineColorBlendStateCreateInfo::builder()
             .attachments(&colorblend_attachments);
-        let pipelinelayout_info = vk::PipelineLayoutCreateInfo::builder();
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<u32>() as u32,
+        }];
+        let pipelinelayout_info = vk::PipelineLayoutCreateInfo::builder()
+            .push_constant_ranges(&push_constant_ranges);
         let pipelinelayout = 
-            unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None) }?;
+            unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None) }
+                .map_err(RendererError::Pipeline)?;
+        let pipelinelayout =
+            CleanupGuard::new(pipelinelayout, |layout: &mut vk::PipelineLayout| unsafe {
+                logical_device.destroy_pipeline_layout(*layout, None)
+            });
+        let mut dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        if settings.dynamic_depth_bias {
+            dynamic_states.push(vk::DynamicState::DEPTH_BIAS);
+        }
+        let dynamic_state_info = vk::PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(&dynamic_states);
         let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
             .stages(&shader_stages)
             .vertex_input_state(&vertex_input_info)
             .input_assembly_state(&input_assembly_info)
             .viewport_state(&viewport_info)
             .rasterization_state(&rasterizer_info)
+            .dynamic_state(&dynamic_state_info)
             .multisample_state(&multisampler_info)
             .color_blend_state(&colorblend_info)
-            .layout(pipelinelayout)
+            .layout(*pipelinelayout)
             .render_pass(*renderpass)
             .subpass(0);
         let graphicspipeline = unsafe {
@@ -133,21 +344,78 @@ impl Pipeline {
                     &[pipeline_info.build()],
                     None,
                 )
-                .expect("A problem with the pipeline creation")
+                .map_err(|(_, err)| RendererError::Pipeline(err))?
         }[0];
-        allocator.free(allocation).unwrap();
-        unsafe {
-            logical_device.destroy_buffer(buffer, None);
-            logical_device.destroy_shader_module(fragmentshader_module, None);
-            logical_device.destroy_shader_module(vertexshader_module, None);
-        }
         Ok(Pipeline { 
             pipeline: graphicspipeline,
-            layout: pipelinelayout,
-            allocator,
+            settings: settings.clone(),
+            channel_view: ChannelView::default(),
+            viewport,
+            scissor,
+            layout: CleanupGuard::into_inner(pipelinelayout),
         })
     }
 
+    /// Fits the viewport and the initial scissor to a new swapchain extent;
+    /// command buffers have to be recorded again to pick it up.
+    pub fn resize(&mut self, extent: vk::Extent2D) {
+        let (viewport, scissor) = viewport_and_scissor(&self.settings, extent);
+        self.viewport = viewport;
+        self.scissor = scissor;
+    }
+
+    /// Records the pipeline's push constants and the initial values of its
+    /// dynamic state; call after binding the pipeline.
+    pub fn set_dynamic_state(&self, logical_device: &ash::Device, commandbuffer: vk::CommandBuffer) {
+        unsafe {
+            logical_device.cmd_push_constants(
+                commandbuffer,
+                self.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                &self.channel_view.shader_value().to_ne_bytes(),
+            );
+        }
+        unsafe { logical_device.cmd_set_viewport(commandbuffer, 0, &[self.viewport]) };
+        self.set_scissor(logical_device, commandbuffer, self.scissor);
+        if self.settings.dynamic_depth_bias {
+            self.set_depth_bias(
+                logical_device,
+                commandbuffer,
+                self.settings.depth_bias.unwrap_or_default(),
+            );
+        }
+    }
+
+    /// Restricts the following draws to `scissor`.
+    pub fn set_scissor(
+        &self,
+        logical_device: &ash::Device,
+        commandbuffer: vk::CommandBuffer,
+        scissor: vk::Rect2D,
+    ) {
+        unsafe { logical_device.cmd_set_scissor(commandbuffer, 0, &[scissor]) };
+    }
+
+    /// Changes the depth bias for the following draws; the pipeline must have
+    /// been created with `dynamic_depth_bias`, and a non-zero `clamp` needs
+    /// the depth_bias_clamp device feature.
+    pub fn set_depth_bias(
+        &self,
+        logical_device: &ash::Device,
+        commandbuffer: vk::CommandBuffer,
+        depth_bias: DepthBias,
+    ) {
+        unsafe {
+            logical_device.cmd_set_depth_bias(
+                commandbuffer,
+                depth_bias.constant_factor,
+                depth_bias.clamp,
+                depth_bias.slope_factor,
+            );
+        }
+    }
+
     pub fn cleanup(&self, logical_device: &ash::Device) {
         unsafe {
             logical_device.destroy_pipeline(self.pipeline, None);
@@ -0,0 +1,162 @@
+use ash::vk;
+
+/// Pixel-space orthographic projection for 2D content: (0, 0) is a corner of
+/// the framebuffer and one unit is one pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Orthographic2D {
+    pub width: f32,
+    pub height: f32,
+    /// Puts the origin in the top-left corner with Y pointing down, like most
+    /// UI and sprite coordinates; otherwise it is bottom-left with Y up.
+    pub y_down: bool,
+}
+
+impl Orthographic2D {
+    pub fn new(extent: vk::Extent2D, y_down: bool) -> Orthographic2D {
+        Orthographic2D {
+            width: extent.width as f32,
+            height: extent.height as f32,
+            y_down,
+        }
+    }
+
+    /// Column-major matrix mapping pixels to Vulkan clip space; depth is
+    /// passed through unchanged so it has to be in [0, 1].
+    pub fn matrix(&self) -> [[f32; 4]; 4] {
+        let y_sign = if self.y_down { 1.0 } else { -1.0 };
+        [
+            [2.0 / self.width, 0.0, 0.0, 0.0],
+            [0.0, y_sign * 2.0 / self.height, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [-1.0, -y_sign, 0.0, 1.0],
+        ]
+    }
+
+    /// Scissor covering the given rectangle in this projection's pixel
+    /// coordinates, grown to whole pixels and clamped to the framebuffer.
+    pub fn scissor(&self, x: f32, y: f32, width: f32, height: f32) -> vk::Rect2D {
+        let (top, bottom) = if self.y_down {
+            (y, y + height)
+        } else {
+            (self.height - (y + height), self.height - y)
+        };
+        let x0 = x.floor().clamp(0.0, self.width);
+        let x1 = (x + width).ceil().clamp(0.0, self.width);
+        let y0 = top.floor().clamp(0.0, self.height);
+        let y1 = bottom.ceil().clamp(0.0, self.height);
+        vk::Rect2D {
+            offset: vk::Offset2D {
+                x: x0 as i32,
+                y: y0 as i32,
+            },
+            extent: vk::Extent2D {
+                width: (x1 - x0) as u32,
+                height: (y1 - y0) as u32,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentationPolicy {
+    /// Fills the whole framebuffer, distorting the aspect ratio if needed.
+    Stretch,
+    /// Scales as large as fits while keeping the aspect ratio, with bars on
+    /// two sides.
+    Letterbox,
+    /// Like `Letterbox`, but only by whole multiples so pixel art stays
+    /// crisp; falls back to `Letterbox` if not even one multiple fits.
+    IntegerScale,
+}
+
+/// A fixed resolution content is authored for, and how it is fitted into
+/// the actual framebuffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LogicalResolution {
+    pub extent: vk::Extent2D,
+    pub policy: PresentationPolicy,
+}
+
+impl LogicalResolution {
+    /// Area of `target` the logical resolution is drawn into; the rest is
+    /// left to the render pass clear color.
+    pub fn viewport(&self, target: vk::Extent2D) -> vk::Rect2D {
+        let scale_x = target.width as f32 / self.extent.width as f32;
+        let scale_y = target.height as f32 / self.extent.height as f32;
+        let fitting_scale = scale_x.min(scale_y);
+        let scale = match self.policy {
+            PresentationPolicy::Stretch => {
+                return vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent: target,
+                }
+            }
+            PresentationPolicy::IntegerScale if fitting_scale >= 1.0 => fitting_scale.floor(),
+            _ => fitting_scale,
+        };
+        let width = ((self.extent.width as f32 * scale).round() as u32).min(target.width);
+        let height = ((self.extent.height as f32 * scale).round() as u32).min(target.height);
+        vk::Rect2D {
+            offset: vk::Offset2D {
+                x: ((target.width - width) / 2) as i32,
+                y: ((target.height - height) / 2) as i32,
+            },
+            extent: vk::Extent2D { width, height },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(matrix: &[[f32; 4]; 4], x: f32, y: f32) -> (f32, f32) {
+        (
+            matrix[0][0] * x + matrix[1][0] * y + matrix[3][0],
+            matrix[0][1] * x + matrix[1][1] * y + matrix[3][1],
+        )
+    }
+
+    #[test]
+    fn corners_map_to_clip_space() {
+        let extent = vk::Extent2D { width: 800, height: 600 };
+        let y_down = Orthographic2D::new(extent, true).matrix();
+        assert_eq!(transform(&y_down, 0.0, 0.0), (-1.0, -1.0));
+        assert_eq!(transform(&y_down, 800.0, 600.0), (1.0, 1.0));
+        let y_up = Orthographic2D::new(extent, false).matrix();
+        assert_eq!(transform(&y_up, 0.0, 0.0), (-1.0, 1.0));
+        assert_eq!(transform(&y_up, 800.0, 600.0), (1.0, -1.0));
+    }
+
+    #[test]
+    fn scissor_is_snapped_outwards_and_clamped() {
+        let extent = vk::Extent2D { width: 100, height: 50 };
+        let scissor = Orthographic2D::new(extent, true).scissor(10.5, 5.2, 20.0, 60.0);
+        assert_eq!(scissor.offset, vk::Offset2D { x: 10, y: 5 });
+        assert_eq!(scissor.extent, vk::Extent2D { width: 21, height: 45 });
+        let scissor = Orthographic2D::new(extent, false).scissor(0.0, 0.0, 10.0, 10.0);
+        assert_eq!(scissor.offset, vk::Offset2D { x: 0, y: 40 });
+        assert_eq!(scissor.extent, vk::Extent2D { width: 10, height: 10 });
+    }
+
+    #[test]
+    fn logical_resolution_policies() {
+        let target = vk::Extent2D { width: 1920, height: 1200 };
+        let logical = |policy| LogicalResolution {
+            extent: vk::Extent2D { width: 320, height: 180 },
+            policy,
+        };
+        let stretched = logical(PresentationPolicy::Stretch).viewport(target);
+        assert_eq!(stretched.extent, target);
+        let letterboxed = logical(PresentationPolicy::Letterbox).viewport(target);
+        assert_eq!(letterboxed.offset, vk::Offset2D { x: 0, y: 60 });
+        assert_eq!(letterboxed.extent, vk::Extent2D { width: 1920, height: 1080 });
+        let integer = logical(PresentationPolicy::IntegerScale).viewport(target);
+        assert_eq!(integer.offset, vk::Offset2D { x: 0, y: 60 });
+        assert_eq!(integer.extent, vk::Extent2D { width: 1920, height: 1080 });
+        let small = vk::Extent2D { width: 1000, height: 700 };
+        let integer = logical(PresentationPolicy::IntegerScale).viewport(small);
+        assert_eq!(integer.offset, vk::Offset2D { x: 20, y: 80 });
+        assert_eq!(integer.extent, vk::Extent2D { width: 960, height: 540 });
+    }
+}
@@ -0,0 +1,133 @@
+use ash::vk;
+
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct Submission {
+    pub commandbuffers: Vec<vk::CommandBuffer>,
+    pub wait_semaphores: Vec<vk::Semaphore>,
+    pub wait_stages: Vec<vk::PipelineStageFlags>,
+    pub signal_semaphores: Vec<vk::Semaphore>,
+}
+
+/// Collects the submissions of a frame for one queue and hands them to the
+/// driver in a single `vkQueueSubmit` on `flush`.
+pub struct QueueRunner {
+    queue: vk::Queue,
+    pending: Vec<Submission>,
+}
+
+impl QueueRunner {
+    pub fn new(queue: vk::Queue) -> QueueRunner {
+        QueueRunner {
+            queue,
+            pending: vec![],
+        }
+    }
+
+    pub fn push(&mut self, submission: Submission) {
+        self.pending.push(submission);
+    }
+
+    /// Submits everything pushed since the last flush; `fence` is signaled
+    /// once all of it has completed.
+    pub fn flush(
+        &mut self,
+        logical_device: &ash::Device,
+        fence: vk::Fence,
+    ) -> Result<(), vk::Result> {
+        let batches = batch_submissions(std::mem::take(&mut self.pending));
+        let submit_infos: Vec<vk::SubmitInfo> = batches
+            .iter()
+            .map(|batch| {
+                vk::SubmitInfo::builder()
+                    .command_buffers(&batch.commandbuffers)
+                    .wait_semaphores(&batch.wait_semaphores)
+                    .wait_dst_stage_mask(&batch.wait_stages)
+                    .signal_semaphores(&batch.signal_semaphores)
+                    .build()
+            })
+            .collect();
+        unsafe { logical_device.queue_submit(self.queue, &submit_infos, fence) }
+    }
+}
+
+/// Merges consecutive submissions into one batch where every wait and signal
+/// stays valid: a submission is folded into the previous one if it does not
+/// wait on anything and the previous one signals nothing. Batches stay in
+/// push order, so semaphores signaled by an earlier batch may be waited on by
+/// a later one.
+pub fn batch_submissions(submissions: Vec<Submission>) -> Vec<Submission> {
+    let mut batches: Vec<Submission> = Vec::with_capacity(submissions.len());
+    for submission in submissions {
+        match batches.last_mut() {
+            Some(previous)
+                if submission.wait_semaphores.is_empty()
+                    && previous.signal_semaphores.is_empty() =>
+            {
+                previous.commandbuffers.extend(submission.commandbuffers);
+                previous.signal_semaphores = submission.signal_semaphores;
+            }
+            _ => batches.push(submission),
+        }
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    fn commandbuffer(raw: u64) -> vk::CommandBuffer {
+        vk::CommandBuffer::from_raw(raw)
+    }
+
+    fn semaphore(raw: u64) -> vk::Semaphore {
+        vk::Semaphore::from_raw(raw)
+    }
+
+    #[test]
+    fn independent_submissions_are_merged() {
+        let batches = batch_submissions(vec![
+            Submission {
+                commandbuffers: vec![commandbuffer(1)],
+                ..Default::default()
+            },
+            Submission {
+                commandbuffers: vec![commandbuffer(2)],
+                signal_semaphores: vec![semaphore(10)],
+                ..Default::default()
+            },
+        ]);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].commandbuffers, vec![commandbuffer(1), commandbuffer(2)]);
+        assert_eq!(batches[0].signal_semaphores, vec![semaphore(10)]);
+    }
+
+    #[test]
+    fn waits_and_signals_split_batches() {
+        let batches = batch_submissions(vec![
+            Submission {
+                commandbuffers: vec![commandbuffer(1)],
+                signal_semaphores: vec![semaphore(10)],
+                ..Default::default()
+            },
+            Submission {
+                commandbuffers: vec![commandbuffer(2)],
+                ..Default::default()
+            },
+            Submission {
+                commandbuffers: vec![commandbuffer(3)],
+                wait_semaphores: vec![semaphore(11)],
+                wait_stages: vec![vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
+                ..Default::default()
+            },
+        ]);
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[2].wait_semaphores, vec![semaphore(11)]);
+    }
+
+    #[test]
+    fn nothing_to_submit() {
+        assert!(batch_submissions(vec![]).is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+
+use super::buffer::{Buffer, SharedAllocator};
+use super::device::{Device, QueueKind};
+use super::error::RendererError;
+
+/// Staging capacity the renderer starts with, in bytes.
+pub const DEFAULT_CAPACITY: u64 = 64 * 1024;
+
+/// Host-visible scratch memory that data is written to before the transfer
+/// queue copies it into a device-local buffer. Grows to fit the largest
+/// upload and is reused between uploads.
+pub struct StagingBuffer {
+    buffer: Option<Buffer>,
+}
+
+impl StagingBuffer {
+    pub fn new(
+        logical_device: &ash::Device,
+        allocator: &SharedAllocator,
+        capacity: u64,
+    ) -> Result<StagingBuffer, RendererError> {
+        let mut staging = StagingBuffer { buffer: None };
+        if capacity > 0 {
+            staging.reserve(logical_device, allocator, capacity)?;
+        }
+        Ok(staging)
+    }
+
+    /// Current size of the host-visible buffer in bytes.
+    pub fn capacity(&self) -> u64 {
+        self.buffer.as_ref().map_or(0, |buffer| buffer.size)
+    }
+
+    /// Returns the host-visible buffer, replacing it first with one of
+    /// `size` bytes if it is smaller than that.
+    fn reserve(
+        &mut self,
+        logical_device: &ash::Device,
+        allocator: &SharedAllocator,
+        size: u64,
+    ) -> Result<&mut Buffer, RendererError> {
+        if self.capacity() < size {
+            self.buffer = None;
+        }
+        let buffer = match self.buffer.take() {
+            Some(buffer) => buffer,
+            None => {
+                let buffer_info = vk::BufferCreateInfo::builder()
+                    .size(size)
+                    .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+                    .sharing_mode(vk::SharingMode::EXCLUSIVE);
+                Buffer::new(
+                    logical_device,
+                    allocator,
+                    "Staging buffer",
+                    &buffer_info,
+                    MemoryLocation::CpuToGpu,
+                )?
+            }
+        };
+        Ok(self.buffer.insert(buffer))
+    }
+
+    /// Copies `data` to the start of `dst` on the transfer queue and blocks
+    /// until the copy has finished, so `dst` can be used by the next
+    /// submission without further synchronization. `dst` needs
+    /// `TRANSFER_DST` usage, must be large enough and, if the transfer family
+    /// is separate, shared with the family that reads it.
+    pub fn upload<T: Copy>(
+        &mut self,
+        device: &Device,
+        allocator: &SharedAllocator,
+        dst: vk::Buffer,
+        data: &[T],
+    ) -> Result<(), RendererError> {
+        let size = std::mem::size_of_val(data) as u64;
+        if size == 0 {
+            return Ok(());
+        }
+        let staging = self.reserve(&device.logical_device, allocator, size)?;
+        staging.write(0, data)?;
+        let src = staging.buffer;
+        let region = vk::BufferCopy {
+            src_offset: 0,
+            dst_offset: 0,
+            size,
+        };
+        let record = |logical_device: &ash::Device, commandbuffer| unsafe {
+            logical_device.cmd_copy_buffer(commandbuffer, src, dst, &[region]);
+        };
+        device
+            .immediate_submit(QueueKind::Transfer, record)
+            .map_err(RendererError::Submit)
+    }
+}
@@ -1,7 +1,8 @@
 use ash::vk;
 
+use super::error::RendererError;
+
 pub struct Surface {
-    xlib_surface_loader: ash::extensions::khr::XlibSurface,
     pub surface: vk::SurfaceKHR,
     surface_loader: ash::extensions::khr::Surface,
 }
@@ -11,25 +12,80 @@ impl Surface {
         window: &winit::window::Window,
         entry: &ash::Entry,
         instance: &ash::Instance,
-    ) -> Result<Surface, vk::Result> {
+    ) -> Result<Surface, RendererError> {
+        let surface = Self::create_platform_surface(window, entry, instance)?;
+        let surface_loader = ash::extensions::khr::Surface::new(entry, instance);
+        Ok(Surface {
+            surface,
+            surface_loader,
+        })
+    }
+
+    /// Instance extensions needed to create a surface on this platform.
+    pub fn required_extensions() -> Vec<*const i8> {
+        vec![
+            ash::extensions::khr::Surface::name().as_ptr(),
+            #[cfg(target_os = "macos")]
+            ash::extensions::ext::MetalSurface::name().as_ptr(),
+            #[cfg(not(target_os = "macos"))]
+            ash::extensions::khr::XlibSurface::name().as_ptr(),
+        ]
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn create_platform_surface(
+        window: &winit::window::Window,
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+    ) -> Result<vk::SurfaceKHR, RendererError> {
         use winit::platform::unix::WindowExtUnix;
-        let x11_display = window.xlib_display().unwrap();
-        let x11_window = window.xlib_window().unwrap();
+        let x11_display = window.xlib_display().ok_or(RendererError::UnsupportedWindow)?;
+        let x11_window = window.xlib_window().ok_or(RendererError::UnsupportedWindow)?;
         let x11_create_info = vk::XlibSurfaceCreateInfoKHR::builder()
             .window(x11_window)
             .dpy(x11_display as *mut vk::Display);
         let xlib_surface_loader = ash::extensions::khr::XlibSurface::new(entry, instance);
-        let surface = unsafe {
+        unsafe {
             xlib_surface_loader.create_xlib_surface(&x11_create_info, None)
-        }?;
-        let surface_loader = ash::extensions::khr::Surface::new(entry, instance);
-        Ok(Surface {
-            xlib_surface_loader,
-            surface,
-            surface_loader,
-        })
+        }.map_err(RendererError::Surface)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn create_platform_surface(
+        window: &winit::window::Window,
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+    ) -> Result<vk::SurfaceKHR, RendererError> {
+        use winit::platform::macos::WindowExtMacOS;
+        let ns_view = window.ns_view();
+        if ns_view.is_null() {
+            return Err(RendererError::UnsupportedWindow);
+        }
+        let layer = unsafe { metal::attach_layer(ns_view, window.scale_factor()) };
+        let metal_create_info = vk::MetalSurfaceCreateInfoEXT::builder()
+            .layer(layer as *const vk::CAMetalLayer);
+        let metal_surface_loader = ash::extensions::ext::MetalSurface::new(entry, instance);
+        unsafe {
+            metal_surface_loader.create_metal_surface(&metal_create_info, None)
+        }.map_err(RendererError::Surface)
+    }
+
+    /// Updates the CAMetalLayer's contents scale after the window moved to a
+    /// display with another DPI scale, so the next swapchain matches it.
+    #[cfg(target_os = "macos")]
+    pub fn set_scale_factor(&self, window: &winit::window::Window, scale_factor: f64) {
+        use winit::platform::macos::WindowExtMacOS;
+        let ns_view = window.ns_view();
+        if !ns_view.is_null() {
+            unsafe { metal::set_contents_scale(ns_view, scale_factor) };
+        }
     }
 
+    /// Only the macOS surface keeps its own scale; elsewhere the swapchain
+    /// extent is all that changes.
+    #[cfg(not(target_os = "macos"))]
+    pub fn set_scale_factor(&self, _window: &winit::window::Window, _scale_factor: f64) {}
+
     pub fn get_surface_capabilities(
         &self, 
         physical_device: vk::PhysicalDevice,
@@ -50,6 +106,20 @@ impl Surface {
         }
     }
 
+    pub fn supports_present(
+        &self,
+        physical_device: vk::PhysicalDevice,
+        queue_family_index: u32,
+    ) -> Result<bool, vk::Result> {
+        unsafe {
+            self.surface_loader.get_physical_device_surface_support(
+                physical_device,
+                queue_family_index,
+                self.surface,
+            )
+        }
+    }
+
     pub fn get_formats(
         &self,
         physical_device: vk::PhysicalDevice,
@@ -68,3 +138,53 @@ impl Drop for Surface {
         }
     }
 }
+
+// MoltenVK presents through a CAMetalLayer, which the NSView winit creates
+// does not have; these are the few Objective-C calls needed to attach one.
+#[cfg(target_os = "macos")]
+mod metal {
+    use std::ffi::c_void;
+    use std::os::raw::c_char;
+
+    type Id = *mut c_void;
+
+    #[link(name = "objc")]
+    extern "C" {
+        fn objc_getClass(name: *const c_char) -> Id;
+        fn sel_registerName(name: *const c_char) -> Id;
+        fn objc_msgSend();
+    }
+
+    #[link(name = "QuartzCore", kind = "framework")]
+    extern "C" {}
+
+    unsafe fn selector(name: &[u8]) -> Id {
+        sel_registerName(name.as_ptr() as *const c_char)
+    }
+
+    /// Makes `ns_view` layer-backed by a new CAMetalLayer and returns it.
+    pub unsafe fn attach_layer(ns_view: Id, scale_factor: f64) -> Id {
+        // objc_msgSend has to be called through the exact signature of the method
+        let msg_send = objc_msgSend as unsafe extern "C" fn();
+        let send: unsafe extern "C" fn(Id, Id) -> Id = std::mem::transmute(msg_send);
+        let send_id: unsafe extern "C" fn(Id, Id, Id) = std::mem::transmute(msg_send);
+        let send_bool: unsafe extern "C" fn(Id, Id, i8) = std::mem::transmute(msg_send);
+
+        let class = objc_getClass(b"CAMetalLayer\0".as_ptr() as *const c_char);
+        let layer = send(class, selector(b"layer\0"));
+        send_bool(ns_view, selector(b"setWantsLayer:\0"), 1);
+        send_id(ns_view, selector(b"setLayer:\0"), layer);
+        set_contents_scale(ns_view, scale_factor);
+        layer
+    }
+
+    /// Sets the contents scale of the layer `attach_layer` gave `ns_view`.
+    pub unsafe fn set_contents_scale(ns_view: Id, scale_factor: f64) {
+        let msg_send = objc_msgSend as unsafe extern "C" fn();
+        let send: unsafe extern "C" fn(Id, Id) -> Id = std::mem::transmute(msg_send);
+        let send_f64: unsafe extern "C" fn(Id, Id, f64) = std::mem::transmute(msg_send);
+
+        let layer = send(ns_view, selector(b"layer\0"));
+        send_f64(layer, selector(b"setContentsScale:\0"), scale_factor);
+    }
+}
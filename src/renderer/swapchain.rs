@@ -1,64 +1,371 @@
 use ash::vk;
+use crate::renderer::cleanup_guard::CleanupGuard;
 use crate::renderer::surface::Surface;
 
 use super::device::Device;
+use super::error::RendererError;
+
+/// How many images the swapchain holds and how many frames the CPU may record
+/// ahead of the GPU. More images and frames in flight smooth out frame time
+/// spikes, but every queued frame adds up to one refresh of input latency.
+#[derive(Clone, Copy, Debug)]
+pub struct SwapchainSettings {
+    pub image_count: u32,
+    pub frames_in_flight: usize,
+    /// Used if the surface supports it, FIFO otherwise.
+    pub present_mode: PresentMode,
+}
+
+impl Default for SwapchainSettings {
+    fn default() -> Self {
+        SwapchainSettings {
+            image_count: 3,
+            frames_in_flight: 2,
+            present_mode: PresentMode::Fifo,
+        }
+    }
+}
+
+/// How finished images are handed to the display.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Waits for vertical blank; always supported.
+    #[default]
+    Fifo,
+    /// Like `Fifo`, but a late image is shown right away and may tear.
+    FifoRelaxed,
+    /// Waits for vertical blank but replaces the queued image with newer
+    /// ones, for low latency without tearing.
+    Mailbox,
+    /// Shows images right away; may tear.
+    Immediate,
+}
+
+impl PresentMode {
+    pub fn to_vk(self) -> vk::PresentModeKHR {
+        match self {
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
+    }
+}
 
 pub struct Swapchain {
     pub swapchain_loader: ash::extensions::khr::Swapchain,
     pub swapchain: vk::SwapchainKHR,
     pub images: Vec<vk::Image>,
     pub image_views: Vec<vk::ImageView>,
+    /// One per image, signaled when the frame drawing into the image
+    /// finished; presentation waits on it. Per image rather than per frame
+    /// slot: the frame fence does not cover presentation's wait, so a slot's
+    /// semaphore could be signaled again before the previous present used it.
+    pub rendering_finished: Vec<vk::Semaphore>,
     pub framebuffers: Vec<vk::Framebuffer>,
     pub surface_format: vk::SurfaceFormatKHR,
     pub extent: vk::Extent2D,
-    pub image_available: Vec<vk::Semaphore>,
-    pub may_begin_drawing: Vec<vk::Fence>,
-    pub rendering_finished: Vec<vk::Semaphore>,
+    /// The present mode actually in use, after checking the preference
+    /// against what the surface supports.
+    pub present_mode: vk::PresentModeKHR,
+    /// One entry per frame in flight, used round-robin.
+    pub frames: Vec<FrameContext>,
     pub amount_of_images: u32,
-    pub current_image: usize,
+    pub frames_in_flight: usize,
+    pub current_frame: usize,
+    pub settings: SwapchainSettings,
+    // Fence of the frame slot that last rendered to each image, if any.
+    // The frame-epoch guards only track which command buffers belong to a
+    // slot; they do not know which image a slot drew into. Images can be
+    // handed out in any order, and there can be more slots than images, so
+    // an acquired image may still be the target of another slot's
+    // submission. acquire waits on that slot's fence before reusing it.
+    images_in_flight: Vec<vk::Fence>,
 }
 
 impl Swapchain {
+    /// Creates the swapchain; `window_extent` is only used if the surface
+    /// leaves the image size up to the swapchain.
     pub fn new(
         instance: &ash::Instance,
         surfaces: &Surface,
         device: &Device,
-    ) -> Result<Swapchain, vk::Result> {
+        settings: &SwapchainSettings,
+        window_extent: vk::Extent2D,
+    ) -> Result<Swapchain, RendererError> {
+        let formats = surfaces
+            .get_formats(device.physical_device)
+            .map_err(RendererError::Swapchain)?;
+        let surface_format = choose_surface_format(&formats).ok_or(RendererError::NoSurfaceFormat)?;
+        let swapchain_loader = ash::extensions::khr::Swapchain::new(instance, &device.logical_device);
+        let swapchain_images = SwapchainImages::create(
+            &swapchain_loader,
+            surfaces,
+            device,
+            settings,
+            surface_format,
+            window_extent,
+            vk::SwapchainKHR::null(),
+        ).map_err(RendererError::Swapchain)?;
+        let swapchain_images =
+            CleanupGuard::new(swapchain_images, |images: &mut SwapchainImages| unsafe {
+                images.destroy(&swapchain_loader, &device.logical_device)
+            });
+        let amount_of_images = swapchain_images.images.len() as u32;
+        let frames_in_flight = settings.frames_in_flight.clamp(1, swapchain_images.images.len());
+        let frames = Vec::with_capacity(frames_in_flight);
+        let mut frames = CleanupGuard::new(frames, |frames: &mut Vec<FrameContext>| unsafe {
+            for frame in frames.iter() {
+                frame.cleanup(&device.logical_device);
+            }
+        });
+        for _ in 0..frames_in_flight {
+            frames.push(FrameContext::new(&device.logical_device).map_err(RendererError::Swapchain)?);
+        }
+        let swapchain_images = CleanupGuard::into_inner(swapchain_images);
+        let frames = CleanupGuard::into_inner(frames);
+        Ok(Swapchain {
+            swapchain_loader,
+            swapchain: swapchain_images.swapchain,
+            images: swapchain_images.images,
+            image_views: swapchain_images.image_views,
+            rendering_finished: swapchain_images.rendering_finished,
+            framebuffers: vec![],
+            extent: swapchain_images.extent,
+            present_mode: swapchain_images.present_mode,
+            surface_format,
+            current_frame: 0,
+            amount_of_images,
+            frames_in_flight,
+            settings: *settings,
+            images_in_flight: vec![vk::Fence::null(); amount_of_images as usize],
+            frames,
+        })
+    }
+
+    /// Replaces the swapchain and its image views after the surface changed,
+    /// e.g. when the window was resized. The framebuffers are destroyed and
+    /// have to be created again with `create_framebuffer`; the GPU must be
+    /// done with the old images. Returns `Minimized` and keeps the old
+    /// swapchain if the surface currently has no area.
+    pub fn recreate(
+        &mut self,
+        surfaces: &Surface,
+        device: &Device,
+        window_extent: vk::Extent2D,
+    ) -> Result<SwapchainStatus, vk::Result> {
+        let surface_capabilities = surfaces.get_surface_capabilities(device.physical_device)?;
+        if is_zero_extent(choose_extent(&surface_capabilities, window_extent)) {
+            return Ok(SwapchainStatus::Minimized);
+        }
+        let swapchain_images = SwapchainImages::create(
+            &self.swapchain_loader,
+            surfaces,
+            device,
+            &self.settings,
+            self.surface_format,
+            window_extent,
+            self.swapchain,
+        )?;
+        unsafe { self.destroy_images(&device.logical_device) };
+        self.swapchain = swapchain_images.swapchain;
+        self.images = swapchain_images.images;
+        self.image_views = swapchain_images.image_views;
+        self.rendering_finished = swapchain_images.rendering_finished;
+        self.extent = swapchain_images.extent;
+        self.present_mode = swapchain_images.present_mode;
+        self.amount_of_images = self.images.len() as u32;
+        self.images_in_flight = vec![vk::Fence::null(); self.images.len()];
+        Ok(SwapchainStatus::Optimal)
+    }
+
+    pub fn create_framebuffer(
+        &mut self,
+        logical_device: &ash::Device,
+        renderpass: vk::RenderPass,
+    ) -> Result<(), vk::Result> {
+        for iv in &self.image_views {
+            let iview = [*iv];
+            let framebuffer_info = vk::FramebufferCreateInfo::builder()
+                .render_pass(renderpass)
+                .attachments(&iview)
+                .width(self.extent.width)
+                .height(self.extent.height)
+                .layers(1);
+            let fb = 
+                unsafe { logical_device.create_framebuffer(&framebuffer_info, None) }?;
+            self.framebuffers.push(fb);
+        }
+        Ok(())
+    }
+
+    /// Waits until the current frame slot is free again and acquires the
+    /// next swapchain image for it. The slot's fence is left signaled; see
+    /// `Frame::reset_fence`.
+    pub fn acquire(&mut self, logical_device: &ash::Device) -> Result<Acquired<'_>, vk::Result> {
+        let may_begin_drawing = [self.frames[self.current_frame].may_begin_drawing];
+        unsafe { logical_device.wait_for_fences(&may_begin_drawing, true, u64::MAX) }?;
+        let acquired = unsafe {
+            self.swapchain_loader.acquire_next_image(
+                self.swapchain,
+                u64::MAX,
+                self.frames[self.current_frame].image_available,
+                vk::Fence::null(),
+            )
+        };
+        let (image_index, suboptimal) = match acquired {
+            Ok(acquired) => acquired,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => return Ok(Acquired::OutOfDate),
+            Err(err) => return Err(err),
+        };
+        // an earlier frame slot may still be rendering to this image
+        let image_in_flight = self.images_in_flight[image_index as usize];
+        if image_in_flight != vk::Fence::null() && image_in_flight != may_begin_drawing[0] {
+            unsafe { logical_device.wait_for_fences(&[image_in_flight], true, u64::MAX) }?;
+        }
+        self.images_in_flight[image_index as usize] = may_begin_drawing[0];
+        Ok(Acquired::Frame(Frame {
+            swapchain: self,
+            image_index,
+            frame_index: self.current_frame,
+            status: if suboptimal {
+                SwapchainStatus::Suboptimal
+            } else {
+                SwapchainStatus::Optimal
+            },
+        }))
+    }
+
+    pub fn advance_frame(&mut self) {
+        self.current_frame = (self.current_frame + 1) % self.frames_in_flight;
+    }
+
+    /// Destroys the swapchain and everything created for it.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with all of it and `logical_device` must be the
+    /// device it was created with.
+    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device) {
+        for frame in &self.frames {
+            frame.cleanup(logical_device);
+        }
+        self.destroy_images(logical_device);
+    }
+
+    unsafe fn destroy_images(&mut self, logical_device: &ash::Device) {
+        for fb in self.framebuffers.drain(..) {
+            logical_device.destroy_framebuffer(fb, None);
+        }
+        for iv in self.image_views.drain(..) {
+            logical_device.destroy_image_view(iv, None);
+        }
+        for semaphore in self.rendering_finished.drain(..) {
+            logical_device.destroy_semaphore(semaphore, None);
+        }
+        self.swapchain_loader
+            .destroy_swapchain(self.swapchain, None)
+    }
+}
+
+/// Synchronization objects of one frame in flight. They are indexed by frame,
+/// not by swapchain image: the image a frame gets is only known after
+/// acquiring.
+pub struct FrameContext {
+    /// Signaled once the acquired image may be rendered to.
+    pub image_available: vk::Semaphore,
+    /// Signaled when the GPU is done with the frame, so its slot and command
+    /// buffers can be reused.
+    pub may_begin_drawing: vk::Fence,
+}
+
+impl FrameContext {
+    fn new(logical_device: &ash::Device) -> Result<FrameContext, vk::Result> {
+        let semaphoreinfo = vk::SemaphoreCreateInfo::builder();
+        let fenceinfo = vk::FenceCreateInfo::builder()
+            .flags(vk::FenceCreateFlags::SIGNALED);
+        let image_available = unsafe { logical_device.create_semaphore(&semaphoreinfo, None) }?;
+        let image_available =
+            CleanupGuard::new(image_available, |semaphore: &mut vk::Semaphore| unsafe {
+                logical_device.destroy_semaphore(*semaphore, None)
+            });
+        let may_begin_drawing = unsafe { logical_device.create_fence(&fenceinfo, None) }?;
+        Ok(FrameContext {
+            image_available: CleanupGuard::into_inner(image_available),
+            may_begin_drawing,
+        })
+    }
+
+    unsafe fn cleanup(&self, logical_device: &ash::Device) {
+        logical_device.destroy_fence(self.may_begin_drawing, None);
+        logical_device.destroy_semaphore(self.image_available, None);
+    }
+}
+
+// the parts of the swapchain that depend on the surface's size
+struct SwapchainImages {
+    swapchain: vk::SwapchainKHR,
+    extent: vk::Extent2D,
+    present_mode: vk::PresentModeKHR,
+    images: Vec<vk::Image>,
+    image_views: Vec<vk::ImageView>,
+    rendering_finished: Vec<vk::Semaphore>,
+}
+
+impl SwapchainImages {
+    fn create(
+        swapchain_loader: &ash::extensions::khr::Swapchain,
+        surfaces: &Surface,
+        device: &Device,
+        settings: &SwapchainSettings,
+        surface_format: vk::SurfaceFormatKHR,
+        window_extent: vk::Extent2D,
+        old_swapchain: vk::SwapchainKHR,
+    ) -> Result<SwapchainImages, vk::Result> {
         let surface_capabilities = surfaces.get_surface_capabilities(device.physical_device)?;
-        let extent = surface_capabilities.current_extent;
+        let image_count = clamp_image_count(settings.image_count, &surface_capabilities);
+        let extent = choose_extent(&surface_capabilities, window_extent);
         let surface_present_modes = surfaces.get_present_modes(device.physical_device)?;
-        let surface_format = *surfaces
-            .get_formats(device.physical_device)?
-            .iter()
-            .find(|surface| surface.format == vk::Format::B8G8R8A8_UNORM)
-            .unwrap();
-        let queuefamilies = [device.queue_families.graphics_q_index.unwrap()];
+        let present_mode = choose_present_mode(&surface_present_modes, settings.present_mode);
+        let queue_families = &device.queue_families;
+        let queuefamilies: Vec<u32> = [queue_families.graphics_q_index, queue_families.present_q_index]
+            .into_iter()
+            .flatten()
+            .collect();
+        // images are drawn on the graphics queue and presented on the present
+        // queue; sharing them avoids ownership transfers between the two
+        let sharing_mode = if queue_families.separate_present_family() {
+            vk::SharingMode::CONCURRENT
+        } else {
+            vk::SharingMode::EXCLUSIVE
+        };
         let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
             .surface(surfaces.surface)
-            // .min_image_count(
-            //     3.max(surface_capabilities.min_image_count)
-            //         .min(surface_capabilities.max_image_count),
-            // )
-            // TODO: From tutorial; does not work... quick fix below (frontbuffer, backbuffer,
-            //       backestbuffer?)
-            .min_image_count(3)
+            .min_image_count(image_count)
             .image_format(surface_format.format)
             .image_color_space(surface_format.color_space)
             .image_extent(extent)
             .image_array_layers(1)
             .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
-            .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .image_sharing_mode(sharing_mode)
             .queue_family_indices(&queuefamilies)
             .pre_transform(surface_capabilities.current_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(vk::PresentModeKHR::FIFO);
-        let swapchain_loader = ash::extensions::khr::Swapchain::new(instance, &device.logical_device);
+            .present_mode(present_mode)
+            .old_swapchain(old_swapchain);
         let swapchain = 
             unsafe { swapchain_loader.create_swapchain(&swapchain_create_info, None)? };
-        let swapchain_images = unsafe { swapchain_loader.get_swapchain_images(swapchain)? };
-        let amount_of_images = swapchain_images.len() as u32;
-        let mut swapchain_imageviews = Vec::with_capacity(swapchain_images.len());
-        for image in &swapchain_images {
+        let swapchain = CleanupGuard::new(swapchain, |swapchain: &mut vk::SwapchainKHR| unsafe {
+            swapchain_loader.destroy_swapchain(*swapchain, None)
+        });
+        let images = unsafe { swapchain_loader.get_swapchain_images(*swapchain)? };
+        let image_views = Vec::with_capacity(images.len());
+        let mut image_views = CleanupGuard::new(image_views, |views: &mut Vec<vk::ImageView>| {
+            for view in views.drain(..) {
+                unsafe { device.logical_device.destroy_image_view(view, None) };
+            }
+        });
+        for image in &images {
             let subresource_range = vk::ImageSubresourceRange::builder()
                 .aspect_mask(vk::ImageAspectFlags::COLOR)
                 .base_mip_level(0)
@@ -68,83 +375,335 @@ impl Swapchain {
             let imageview_create_info = vk::ImageViewCreateInfo::builder()
                 .image(*image)
                 .view_type(vk::ImageViewType::TYPE_2D)
-                .format(vk::Format::B8G8R8A8_UNORM)
+                .format(surface_format.format)
                 .subresource_range(*subresource_range);
             let imageview = 
                 unsafe { device.logical_device.create_image_view(&imageview_create_info, None) }?;
-            swapchain_imageviews.push(imageview);
+            image_views.push(imageview);
         }
-        let mut image_available = vec![];
-        let mut rendering_finished = vec![];
-        let mut may_begin_drawing = vec![];
         let semaphoreinfo = vk::SemaphoreCreateInfo::builder();
-        let fenceinfo = vk::FenceCreateInfo::builder()
-            .flags(vk::FenceCreateFlags::SIGNALED);
-        for _ in 0..amount_of_images {
-            let semaphore_available =
-                unsafe { device.logical_device.create_semaphore(&semaphoreinfo, None) }?;
-            let semaphore_finished =
+        let rendering_finished = Vec::with_capacity(images.len());
+        let mut rendering_finished =
+            CleanupGuard::new(rendering_finished, |semaphores: &mut Vec<vk::Semaphore>| {
+                for semaphore in semaphores.drain(..) {
+                    unsafe { device.logical_device.destroy_semaphore(semaphore, None) };
+                }
+            });
+        for _ in &images {
+            let semaphore =
                 unsafe { device.logical_device.create_semaphore(&semaphoreinfo, None) }?;
-            image_available.push(semaphore_available);
-            rendering_finished.push(semaphore_finished);
-            let fence =
-                unsafe { device.logical_device.create_fence(&fenceinfo, None) }?;
-            may_begin_drawing.push(fence);
+            rendering_finished.push(semaphore);
         }
-        Ok(Swapchain {
-            swapchain_loader,
-            swapchain,
-            images: swapchain_images,
-            image_views: swapchain_imageviews,
-            framebuffers: vec![],
+        Ok(SwapchainImages {
+            swapchain: CleanupGuard::into_inner(swapchain),
             extent,
-            surface_format,
-            current_image: 0,
-            amount_of_images,
-            image_available,
-            rendering_finished,
-            may_begin_drawing,
+            present_mode,
+            images,
+            image_views: CleanupGuard::into_inner(image_views),
+            rendering_finished: CleanupGuard::into_inner(rendering_finished),
         })
     }
 
-    pub fn create_framebuffer(
+    unsafe fn destroy(
         &mut self,
+        swapchain_loader: &ash::extensions::khr::Swapchain,
         logical_device: &ash::Device,
-        renderpass: vk::RenderPass,
-    ) -> Result<(), vk::Result> {
-        for iv in &self.image_views {
-            let iview = [*iv];
-            let framebuffer_info = vk::FramebufferCreateInfo::builder()
-                .render_pass(renderpass)
-                .attachments(&iview)
-                .width(self.extent.width)
-                .height(self.extent.height)
-                .layers(1);
-            let fb = 
-                unsafe { logical_device.create_framebuffer(&framebuffer_info, None) }?;
-            self.framebuffers.push(fb);
+    ) {
+        for semaphore in self.rendering_finished.drain(..) {
+            logical_device.destroy_semaphore(semaphore, None);
         }
-        Ok(())
+        for view in self.image_views.drain(..) {
+            logical_device.destroy_image_view(view, None);
+        }
+        swapchain_loader.destroy_swapchain(self.swapchain, None);
     }
+}
 
-    pub unsafe fn cleanup(&mut self, logical_device: &ash::Device) {
-        for fence in &self.may_begin_drawing {
-            logical_device.destroy_fence(*fence, None);
-        }
-        for semaphore in &self.image_available {
-            logical_device.destroy_semaphore(*semaphore, None);
-        }
-        for semaphore in &self.rendering_finished {
-            logical_device.destroy_semaphore(*semaphore, None);
-        }
-        for fb in &self.framebuffers {
-            logical_device.destroy_framebuffer(*fb, None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapchainStatus {
+    Optimal,
+    Suboptimal,
+    OutOfDate,
+    /// The surface has no area, e.g. because the window is minimized; there
+    /// is nothing to draw into until it grows again.
+    Minimized,
+}
+
+pub enum Acquired<'a> {
+    Frame(Frame<'a>),
+    OutOfDate,
+}
+
+/// An acquired swapchain image together with the synchronization objects of
+/// the frame slot it was acquired for.
+pub struct Frame<'a> {
+    swapchain: &'a Swapchain,
+    pub image_index: u32,
+    pub frame_index: usize,
+    pub status: SwapchainStatus,
+}
+
+impl<'a> Frame<'a> {
+    pub fn image_available(&self) -> vk::Semaphore {
+        self.swapchain.frames[self.frame_index].image_available
+    }
+
+    pub fn rendering_finished(&self) -> vk::Semaphore {
+        self.swapchain.rendering_finished[self.image_index as usize]
+    }
+
+    pub fn may_begin_drawing(&self) -> vk::Fence {
+        self.swapchain.frames[self.frame_index].may_begin_drawing
+    }
+
+    /// Unsignals `may_begin_drawing`. Call it right before the submission
+    /// that signals it again: if the frame is abandoned before that, the
+    /// fence stays signaled and the next `acquire` of this slot does not
+    /// block forever. If that submission fails, call `signal_fence`.
+    pub fn reset_fence(&self, logical_device: &ash::Device) -> Result<(), vk::Result> {
+        unsafe { logical_device.reset_fences(&[self.may_begin_drawing()]) }
+    }
+
+    /// Signals `may_begin_drawing` with an empty submission to `queue`, for
+    /// when the frame's own submission failed after `reset_fence`.
+    pub fn signal_fence(
+        &self,
+        logical_device: &ash::Device,
+        queue: vk::Queue,
+    ) -> Result<(), vk::Result> {
+        unsafe { logical_device.queue_submit(queue, &[], self.may_begin_drawing()) }
+    }
+
+    pub fn framebuffer(&self) -> vk::Framebuffer {
+        self.swapchain.framebuffers[self.image_index as usize]
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.swapchain.extent
+    }
+
+    /// Presents the image once `rendering_finished` is signaled.
+    pub fn present(self, queue: vk::Queue) -> Result<SwapchainStatus, vk::Result> {
+        let semaphores_finished = [self.rendering_finished()];
+        let swapchains = [self.swapchain.swapchain];
+        let indices = [self.image_index];
+        let present_info = vk::PresentInfoKHR::builder()
+            .wait_semaphores(&semaphores_finished)
+            .swapchains(&swapchains)
+            .image_indices(&indices);
+        match unsafe { self.swapchain.swapchain_loader.queue_present(queue, &present_info) } {
+            Ok(false) => Ok(SwapchainStatus::Optimal),
+            Ok(true) => Ok(SwapchainStatus::Suboptimal),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(SwapchainStatus::OutOfDate),
+            Err(err) => Err(err),
         }
-        for iv in &self.image_views {
-            logical_device.destroy_image_view(*iv, None);
+    }
+}
+
+/// Clamps the requested image count into what the surface supports; a
+/// `max_image_count` of zero means there is no upper limit.
+pub fn clamp_image_count(requested: u32, capabilities: &vk::SurfaceCapabilitiesKHR) -> u32 {
+    let image_count = requested.max(capabilities.min_image_count);
+    if capabilities.max_image_count > 0 {
+        image_count.min(capabilities.max_image_count)
+    } else {
+        image_count
+    }
+}
+
+/// The surface's current extent, or `window_extent` clamped into the
+/// supported range if the surface lets the swapchain decide (a current extent
+/// of `u32::MAX`).
+pub fn choose_extent(
+    capabilities: &vk::SurfaceCapabilitiesKHR,
+    window_extent: vk::Extent2D,
+) -> vk::Extent2D {
+    if capabilities.current_extent.width != u32::MAX {
+        return capabilities.current_extent;
+    }
+    vk::Extent2D {
+        width: window_extent.width.clamp(
+            capabilities.min_image_extent.width,
+            capabilities.max_image_extent.width,
+        ),
+        height: window_extent.height.clamp(
+            capabilities.min_image_extent.height,
+            capabilities.max_image_extent.height,
+        ),
+    }
+}
+
+/// Swapchain formats in order of preference. The sRGB ones come first so
+/// shaders can write linear colors and have them encoded on store.
+pub const PREFERRED_SURFACE_FORMATS: [vk::Format; 4] = [
+    vk::Format::B8G8R8A8_SRGB,
+    vk::Format::R8G8B8A8_SRGB,
+    vk::Format::B8G8R8A8_UNORM,
+    vk::Format::R8G8B8A8_UNORM,
+];
+
+/// A swapchain can not be created for an extent without area.
+pub fn is_zero_extent(extent: vk::Extent2D) -> bool {
+    extent.width == 0 || extent.height == 0
+}
+
+/// Picks the swapchain format from `PREFERRED_SURFACE_FORMATS` in the sRGB
+/// color space, falling back to whatever the surface lists first.
+pub fn choose_surface_format(formats: &[vk::SurfaceFormatKHR]) -> Option<vk::SurfaceFormatKHR> {
+    // a single UNDEFINED entry means the surface has no preferred format
+    if let [only] = formats {
+        if only.format == vk::Format::UNDEFINED {
+            return Some(vk::SurfaceFormatKHR {
+                format: PREFERRED_SURFACE_FORMATS[0],
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            });
         }
-        self.swapchain_loader
-            .destroy_swapchain(self.swapchain, None)
     }
+    PREFERRED_SURFACE_FORMATS
+        .iter()
+        .find_map(|preferred| {
+            formats.iter().find(|surface| {
+                surface.format == *preferred
+                    && surface.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+            })
+        })
+        .or_else(|| formats.first())
+        .copied()
 }
 
+/// Returns `preferred` if the surface supports it, otherwise FIFO, which every
+/// surface has to support.
+pub fn choose_present_mode(
+    present_modes: &[vk::PresentModeKHR],
+    preferred: PresentMode,
+) -> vk::PresentModeKHR {
+    if present_modes.contains(&preferred.to_vk()) {
+        preferred.to_vk()
+    } else {
+        vk::PresentModeKHR::FIFO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(format: vk::Format) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        }
+    }
+
+    #[test]
+    fn bgra_srgb_is_preferred() {
+        let formats = [
+            format(vk::Format::R8G8B8A8_UNORM),
+            format(vk::Format::B8G8R8A8_UNORM),
+            format(vk::Format::B8G8R8A8_SRGB),
+        ];
+        assert_eq!(choose_surface_format(&formats).unwrap().format, vk::Format::B8G8R8A8_SRGB);
+    }
+
+    #[test]
+    fn rgba_surface_falls_back_to_rgba_srgb() {
+        let formats = [
+            format(vk::Format::R8G8B8A8_UNORM),
+            format(vk::Format::R8G8B8A8_SRGB),
+        ];
+        assert_eq!(choose_surface_format(&formats).unwrap().format, vk::Format::R8G8B8A8_SRGB);
+    }
+
+    #[test]
+    fn unorm_is_used_without_srgb() {
+        let formats = [
+            format(vk::Format::A2B10G10R10_UNORM_PACK32),
+            format(vk::Format::R8G8B8A8_UNORM),
+            format(vk::Format::B8G8R8A8_UNORM),
+        ];
+        assert_eq!(choose_surface_format(&formats).unwrap().format, vk::Format::B8G8R8A8_UNORM);
+    }
+
+    #[test]
+    fn other_color_spaces_are_not_preferred() {
+        let formats = [
+            vk::SurfaceFormatKHR {
+                format: vk::Format::B8G8R8A8_SRGB,
+                color_space: vk::ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT,
+            },
+            format(vk::Format::R8G8B8A8_UNORM),
+        ];
+        assert_eq!(choose_surface_format(&formats).unwrap().format, vk::Format::R8G8B8A8_UNORM);
+    }
+
+    #[test]
+    fn unknown_formats_fall_back_to_first() {
+        let formats = [
+            format(vk::Format::A2B10G10R10_UNORM_PACK32),
+            format(vk::Format::R16G16B16A16_SFLOAT),
+        ];
+        assert_eq!(
+            choose_surface_format(&formats).unwrap().format,
+            vk::Format::A2B10G10R10_UNORM_PACK32,
+        );
+    }
+
+    #[test]
+    fn undefined_format_means_any() {
+        let formats = [format(vk::Format::UNDEFINED)];
+        assert_eq!(choose_surface_format(&formats).unwrap().format, vk::Format::B8G8R8A8_SRGB);
+    }
+
+    #[test]
+    fn no_formats_yields_none() {
+        assert_eq!(choose_surface_format(&[]), None);
+    }
+
+    #[test]
+    fn image_count_respects_surface_limits() {
+        let capabilities = vk::SurfaceCapabilitiesKHR {
+            min_image_count: 2,
+            max_image_count: 0,
+            ..Default::default()
+        };
+        assert_eq!(clamp_image_count(1, &capabilities), 2);
+        assert_eq!(clamp_image_count(8, &capabilities), 8);
+        let capabilities = vk::SurfaceCapabilitiesKHR {
+            min_image_count: 2,
+            max_image_count: 3,
+            ..Default::default()
+        };
+        assert_eq!(clamp_image_count(4, &capabilities), 3);
+    }
+
+    #[test]
+    fn extent_follows_the_surface_unless_left_open() {
+        let mut capabilities = vk::SurfaceCapabilitiesKHR {
+            current_extent: vk::Extent2D { width: 800, height: 600 },
+            min_image_extent: vk::Extent2D { width: 1, height: 1 },
+            max_image_extent: vk::Extent2D { width: 4096, height: 4096 },
+            ..Default::default()
+        };
+        let window_extent = vk::Extent2D { width: 1024, height: 8192 };
+        assert_eq!(choose_extent(&capabilities, window_extent), capabilities.current_extent);
+        capabilities.current_extent = vk::Extent2D { width: u32::MAX, height: u32::MAX };
+        assert_eq!(
+            choose_extent(&capabilities, window_extent),
+            vk::Extent2D { width: 1024, height: 4096 },
+        );
+    }
+
+    #[test]
+    fn present_mode_falls_back_to_fifo() {
+        let modes = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::IMMEDIATE];
+        assert_eq!(
+            choose_present_mode(&modes, PresentMode::Immediate),
+            vk::PresentModeKHR::IMMEDIATE,
+        );
+        assert_eq!(
+            choose_present_mode(&modes, PresentMode::Mailbox),
+            vk::PresentModeKHR::FIFO,
+        );
+    }
+}
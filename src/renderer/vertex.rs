@@ -0,0 +1,140 @@
+use ash::vk;
+
+use super::buffer::{Buffer, SharedAllocator};
+use super::device::Device;
+use super::error::RendererError;
+use super::staging::StagingBuffer;
+
+/// A vertex type that can be uploaded into a [`VertexBuffer`] and described
+/// to a pipeline.
+pub trait Vertex: Copy {
+    fn binding_description() -> vk::VertexInputBindingDescription;
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription>;
+}
+
+/// The vertex the built-in shaders expect: a 2D position in normalized
+/// device coordinates, an RGBA color and texture coordinates.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColoredVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+    pub uv: [f32; 2],
+}
+
+impl Vertex for ColoredVertex {
+    fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<ColoredVertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }
+    }
+
+    fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: std::mem::offset_of!(ColoredVertex, position) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: std::mem::offset_of!(ColoredVertex, color) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 2,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: std::mem::offset_of!(ColoredVertex, uv) as u32,
+            },
+        ]
+    }
+}
+
+/// The triangle drawn when the application adds no meshes of its own.
+pub const TRIANGLE: [ColoredVertex; 3] = [
+    ColoredVertex { position: [0.0, -0.6], color: [1.0, 0.2, 0.2, 1.0], uv: [0.5, 0.0] },
+    ColoredVertex { position: [0.6, 0.6], color: [0.2, 1.0, 0.2, 1.0], uv: [1.0, 1.0] },
+    ColoredVertex { position: [-0.6, 0.6], color: [0.2, 0.4, 1.0, 1.0], uv: [0.0, 1.0] },
+];
+
+/// Vertices in a buffer the GPU reads from, either host-visible or
+/// device-local.
+pub struct VertexBuffer {
+    pub buffer: Buffer,
+    pub vertex_count: u32,
+}
+
+impl VertexBuffer {
+    /// Creates a host-visible buffer for `vertices` and copies them in;
+    /// `vertices` must not be empty.
+    pub fn new<V: Vertex>(
+        logical_device: &ash::Device,
+        allocator: &SharedAllocator,
+        vertices: &[V],
+    ) -> Result<VertexBuffer, RendererError> {
+        let buffer = Buffer::host_visible(
+            logical_device,
+            allocator,
+            "Vertex buffer",
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vertices,
+        )?;
+        Ok(VertexBuffer {
+            buffer,
+            vertex_count: vertices.len() as u32,
+        })
+    }
+
+    /// Creates a device-local buffer for `vertices` and fills it through
+    /// `staging` on the transfer queue; `vertices` must not be empty.
+    pub fn new_device_local<V: Vertex>(
+        device: &Device,
+        allocator: &SharedAllocator,
+        staging: &mut StagingBuffer,
+        vertices: &[V],
+    ) -> Result<VertexBuffer, RendererError> {
+        let buffer = Buffer::device_local(
+            device,
+            allocator,
+            staging,
+            "Vertex buffer",
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vertices,
+        )?;
+        Ok(VertexBuffer {
+            buffer,
+            vertex_count: vertices.len() as u32,
+        })
+    }
+
+    pub fn bind(&self, logical_device: &ash::Device, commandbuffer: vk::CommandBuffer) {
+        unsafe { logical_device.cmd_bind_vertex_buffers(commandbuffer, 0, &[self.buffer.buffer], &[0]) };
+    }
+
+    /// Binds the buffer and draws all of its vertices.
+    pub fn draw(&self, logical_device: &ash::Device, commandbuffer: vk::CommandBuffer) {
+        self.bind(logical_device, commandbuffer);
+        unsafe { logical_device.cmd_draw(commandbuffer, self.vertex_count, 1, 0, 0) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colored_vertex_attributes_cover_the_struct() {
+        let binding = ColoredVertex::binding_description();
+        let attributes = ColoredVertex::attribute_descriptions();
+        assert_eq!(binding.stride, 32);
+        let offsets: Vec<u32> = attributes.iter().map(|attribute| attribute.offset).collect();
+        assert_eq!(offsets, vec![0, 8, 24]);
+        let locations: Vec<u32> = attributes.iter().map(|attribute| attribute.location).collect();
+        assert_eq!(locations, vec![0, 1, 2]);
+    }
+}
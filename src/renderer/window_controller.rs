@@ -0,0 +1,99 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use winit::error::ExternalError;
+use winit::window::{BadIcon, Icon, Window};
+
+/// Window-level controls for interactive applications: cursor capture,
+/// title, icon and files dropped onto the window.
+pub struct WindowController {
+    window: Window,
+    title: String,
+    show_fps: bool,
+    frames_since_update: u32,
+    last_fps_update: Instant,
+    cursor_grabbed: bool,
+    cursor_visible: bool,
+    dropped_files: Vec<PathBuf>,
+}
+
+impl WindowController {
+    pub fn new(window: Window) -> WindowController {
+        WindowController {
+            window,
+            title: String::new(),
+            show_fps: false,
+            frames_since_update: 0,
+            last_fps_update: Instant::now(),
+            cursor_grabbed: false,
+            cursor_visible: true,
+            dropped_files: vec![],
+        }
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_owned();
+        self.window.set_title(title);
+    }
+
+    /// Appends the frame rate to the title, updated once per second from
+    /// `frame_presented`.
+    pub fn set_show_fps(&mut self, show_fps: bool) {
+        self.show_fps = show_fps;
+        if !show_fps {
+            self.window.set_title(&self.title);
+        }
+    }
+
+    pub fn frame_presented(&mut self) {
+        self.frames_since_update += 1;
+        let elapsed = self.last_fps_update.elapsed();
+        if !self.show_fps || elapsed < Duration::from_secs(1) {
+            return;
+        }
+        let fps = self.frames_since_update as f64 / elapsed.as_secs_f64();
+        self.window.set_title(&format!("{} - {:.0} fps", self.title, fps));
+        self.frames_since_update = 0;
+        self.last_fps_update = Instant::now();
+    }
+
+    /// Sets the window icon from tightly packed RGBA8 pixels.
+    pub fn set_icon(&self, rgba: Vec<u8>, width: u32, height: u32) -> Result<(), BadIcon> {
+        let icon = Icon::from_rgba(rgba, width, height)?;
+        self.window.set_window_icon(Some(icon));
+        Ok(())
+    }
+
+    /// Confines the cursor to the window, e.g. for a first-person camera.
+    pub fn set_cursor_grab(&mut self, grab: bool) -> Result<(), ExternalError> {
+        self.window.set_cursor_grab(grab)?;
+        self.cursor_grabbed = grab;
+        Ok(())
+    }
+
+    pub fn cursor_grabbed(&self) -> bool {
+        self.cursor_grabbed
+    }
+
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.window.set_cursor_visible(visible);
+        self.cursor_visible = visible;
+    }
+
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    pub fn file_dropped(&mut self, path: PathBuf) {
+        self.dropped_files.push(path);
+    }
+
+    /// Files dropped onto the window since the last call.
+    pub fn take_dropped_files(&mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.dropped_files)
+    }
+}